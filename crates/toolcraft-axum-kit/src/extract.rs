@@ -0,0 +1,370 @@
+use axum::{
+    body::Bytes,
+    extract::{FromRequest, FromRequestParts, Request},
+    http::{HeaderMap, StatusCode, header, request::Parts},
+};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use validator::{Validate, ValidationErrors, ValidationErrorsKind};
+
+use crate::response::{ApiError, api_error};
+
+/// A single failing field, listed in the `data` of a `CommonError`.
+///
+/// `field` is a dotted path such as `address.zip` or `items[0].name`; it is empty when the error
+/// is not tied to a field (for example, malformed JSON).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// JSON body extractor that deserializes and validates `T`.
+///
+/// Rejections are `CommonError`s: `415` for a wrong content type, `400` for malformed JSON and
+/// `422` for type mismatches or failed validation rules, with the failing fields in `data`.
+///
+/// ```rust,ignore
+/// #[derive(Deserialize, Validate)]
+/// struct CreateUser {
+///     #[validate(length(min = 1, max = 64))]
+///     name: String,
+///     #[validate(email)]
+///     email: String,
+/// }
+///
+/// async fn create_user(ValidJson(body): ValidJson<CreateUser>) -> ResponseResult<User> { .. }
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ValidJson<T>(pub T);
+
+/// Query string extractor that deserializes and validates `T`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ValidQuery<T>(pub T);
+
+/// `application/x-www-form-urlencoded` body extractor that deserializes and validates `T`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ValidForm<T>(pub T);
+
+impl<T, S> FromRequest<S> for ValidJson<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        if !is_json_content_type(req.headers()) {
+            return Err(api_error(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "expected request with `Content-Type: application/json`",
+            ));
+        }
+        let bytes = read_body(req, state).await?;
+        let mut deserializer = serde_json::Deserializer::from_slice(&bytes);
+        let value: T = serde_path_to_error::deserialize(&mut deserializer).map_err(|e| {
+            let status = match e.inner().classify() {
+                serde_json::error::Category::Data => StatusCode::UNPROCESSABLE_ENTITY,
+                _ => StatusCode::BAD_REQUEST,
+            };
+            deserialize_error(status, "invalid request body", e)
+        })?;
+        // Reject trailing characters after the value, as axum's `Json` does.
+        deserializer.end().map_err(|e| {
+            let field = FieldError {
+                field: String::new(),
+                message: e.to_string(),
+            };
+            with_fields(
+                api_error(StatusCode::BAD_REQUEST, "invalid request body"),
+                vec![field],
+            )
+        })?;
+        validate(value).map(ValidJson)
+    }
+}
+
+impl<T, S> FromRequestParts<S> for ValidQuery<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let query = parts.uri.query().unwrap_or_default();
+        let value = deserialize_urlencoded(query.as_bytes(), "invalid query string")?;
+        validate(value).map(ValidQuery)
+    }
+}
+
+impl<T, S> FromRequest<S> for ValidForm<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        if content_type(req.headers()) != Some("application/x-www-form-urlencoded") {
+            return Err(api_error(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "expected request with `Content-Type: application/x-www-form-urlencoded`",
+            ));
+        }
+        let bytes = read_body(req, state).await?;
+        let value = deserialize_urlencoded(&bytes, "invalid form body")?;
+        validate(value).map(ValidForm)
+    }
+}
+
+async fn read_body<S>(req: Request, state: &S) -> Result<Bytes, ApiError>
+where
+    S: Send + Sync,
+{
+    Bytes::from_request(req, state)
+        .await
+        .map_err(|e| api_error(e.status(), e.body_text()))
+}
+
+fn deserialize_urlencoded<T>(input: &[u8], message: &str) -> Result<T, ApiError>
+where
+    T: DeserializeOwned,
+{
+    let deserializer = serde_urlencoded::Deserializer::new(form_urlencoded::parse(input));
+    serde_path_to_error::deserialize(deserializer)
+        .map_err(|e| deserialize_error(StatusCode::BAD_REQUEST, message, e))
+}
+
+fn deserialize_error<E>(
+    status: StatusCode,
+    message: &str,
+    error: serde_path_to_error::Error<E>,
+) -> ApiError
+where
+    E: std::fmt::Display,
+{
+    let path = error.path().to_string();
+    let field = FieldError {
+        field: if path == "." { String::new() } else { path },
+        message: error.inner().to_string(),
+    };
+    with_fields(api_error(status, message), vec![field])
+}
+
+fn validate<T>(value: T) -> Result<T, ApiError>
+where
+    T: Validate,
+{
+    value.validate().map(|_| value).map_err(|errors| {
+        let mut fields = Vec::new();
+        collect_field_errors("", &errors, &mut fields);
+        fields.sort_by(|a, b| a.field.cmp(&b.field));
+        with_fields(
+            api_error(StatusCode::UNPROCESSABLE_ENTITY, "validation failed"),
+            fields,
+        )
+    })
+}
+
+fn collect_field_errors(prefix: &str, errors: &ValidationErrors, out: &mut Vec<FieldError>) {
+    for (field, kind) in errors.errors() {
+        let path = match (prefix.is_empty(), field.as_ref()) {
+            (_, "__all__") => prefix.to_string(),
+            (true, field) => field.to_string(),
+            (false, field) => format!("{prefix}.{field}"),
+        };
+        match kind {
+            ValidationErrorsKind::Field(errors) => {
+                out.extend(errors.iter().map(|e| {
+                    FieldError {
+                        field: path.clone(),
+                        message: e
+                            .message
+                            .as_ref()
+                            .map(|m| m.to_string())
+                            .unwrap_or_else(|| format!("failed `{}` validation", e.code)),
+                    }
+                }));
+            }
+            ValidationErrorsKind::Struct(errors) => collect_field_errors(&path, errors, out),
+            ValidationErrorsKind::List(items) => {
+                for (index, errors) in items {
+                    collect_field_errors(&format!("{path}[{index}]"), errors, out);
+                }
+            }
+        }
+    }
+}
+
+fn with_fields(error: ApiError, fields: Vec<FieldError>) -> ApiError {
+    let (status, axum::Json(error)) = error;
+    (status, error.with_data(fields).to_json())
+}
+
+fn content_type(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.split(';').next().unwrap_or_default().trim())
+}
+
+fn is_json_content_type(headers: &HeaderMap) -> bool {
+    content_type(headers).is_some_and(|ct| {
+        ct == "application/json" || (ct.starts_with("application/") && ct.ends_with("+json"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        Router,
+        body::{Body, to_bytes},
+        http::Request,
+        routing::{get, post},
+    };
+    use serde_json::{Value, json};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize, Validate)]
+    struct Address {
+        #[validate(length(equal = 5, message = "zip must be 5 characters"))]
+        zip: String,
+    }
+
+    #[derive(Debug, Deserialize, Validate)]
+    struct CreateUser {
+        #[validate(length(min = 1))]
+        name: String,
+        #[validate(range(min = 18))]
+        age: u8,
+        #[validate(nested)]
+        address: Address,
+    }
+
+    #[derive(Debug, Deserialize, Validate)]
+    struct Search {
+        #[validate(length(min = 3))]
+        q: String,
+    }
+
+    fn app() -> Router {
+        Router::new()
+            .route(
+                "/users",
+                post(|ValidJson(user): ValidJson<CreateUser>| async move { user.name }),
+            )
+            .route(
+                "/search",
+                get(|ValidQuery(search): ValidQuery<Search>| async move { search.q }),
+            )
+            .route(
+                "/login",
+                post(|ValidForm(search): ValidForm<Search>| async move { search.q }),
+            )
+    }
+
+    async fn send(request: Request<Body>) -> (StatusCode, Value) {
+        let response = app().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (
+            status,
+            serde_json::from_slice(&body)
+                .unwrap_or(Value::String(String::from_utf8_lossy(&body).into_owned())),
+        )
+    }
+
+    fn json_request(body: Value) -> Request<Body> {
+        Request::post("/users")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_valid_json_accepts_valid_body() {
+        let (status, body) = send(json_request(
+            json!({"name": "alice", "age": 30, "address": {"zip": "12345"}}),
+        ))
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "alice");
+    }
+
+    #[tokio::test]
+    async fn test_valid_json_lists_failing_fields() {
+        let (status, body) = send(json_request(
+            json!({"name": "", "age": 12, "address": {"zip": "1"}}),
+        ))
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["code"], 422);
+        assert_eq!(body["message"], "validation failed");
+        assert_eq!(
+            body["data"],
+            json!([
+                {"field": "address.zip", "message": "zip must be 5 characters"},
+                {"field": "age", "message": "failed `range` validation"},
+                {"field": "name", "message": "failed `length` validation"},
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_valid_json_deserialize_errors_use_envelope() {
+        let (status, body) = send(json_request(
+            json!({"name": "alice", "age": "old", "address": {"zip": "12345"}}),
+        ))
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["data"][0]["field"], "age");
+
+        let request = Request::post("/users")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from("{"))
+            .unwrap();
+        let (status, body) = send(request).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["message"], "invalid request body");
+
+        let request = Request::post("/users")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(
+                r#"{"name": "alice", "age": 30, "address": {"zip": "12345"}} garbage"#,
+            ))
+            .unwrap();
+        let (status, body) = send(request).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["message"], "invalid request body");
+        assert_eq!(body["data"][0]["field"], "");
+
+        let request = Request::post("/users").body(Body::from("{}")).unwrap();
+        let (status, body) = send(request).await;
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(body["code"], 415);
+    }
+
+    #[tokio::test]
+    async fn test_valid_query_and_form() {
+        let request = Request::get("/search?q=ab").body(Body::empty()).unwrap();
+        let (status, body) = send(request).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["data"][0]["field"], "q");
+
+        let request = Request::get("/search").body(Body::empty()).unwrap();
+        let (status, body) = send(request).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["message"], "invalid query string");
+
+        let request = Request::post("/login")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from("q=rust"))
+            .unwrap();
+        let (status, body) = send(request).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "rust");
+    }
+}
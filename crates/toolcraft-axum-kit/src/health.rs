@@ -0,0 +1,382 @@
+use std::{
+    future::Future,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use axum::{
+    Json, Router,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use futures_util::future::{BoxFuture, join_all};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Error, Result},
+    response::{CommonError, IntoCommonResponse},
+};
+
+/// A dependency that must be available for the instance to receive traffic.
+pub trait HealthCheck: Send + Sync + 'static {
+    /// Name reported in the readiness body.
+    fn name(&self) -> &str;
+
+    fn check(&self) -> BoxFuture<'_, Result<()>>;
+}
+
+/// A [`HealthCheck`] running an async closure.
+///
+/// ```rust,ignore
+/// let db = check_fn("postgres", move || {
+///     let pool = pool.clone();
+///     async move { pool.ping().await.map_err(|e| Error::ErrorMessage(e.to_string().into())) }
+/// });
+/// ```
+pub fn check_fn<F, Fut>(name: impl Into<String>, check: F) -> FnCheck<F>
+where
+    F: Fn() -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<()>> + Send + 'static,
+{
+    FnCheck {
+        name: name.into(),
+        check,
+    }
+}
+
+/// Returned by [`check_fn`].
+pub struct FnCheck<F> {
+    name: String,
+    check: F,
+}
+
+impl<F, Fut> HealthCheck for FnCheck<F>
+where
+    F: Fn() -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<()>> + Send + 'static,
+{
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn check(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin((self.check)())
+    }
+}
+
+/// A check that fails until [`FlagCheck::set`] marks it ready, for state such as loaded config or
+/// warmed caches.
+#[derive(Debug, Clone)]
+pub struct FlagCheck {
+    name: String,
+    ready: Arc<AtomicBool>,
+}
+
+impl FlagCheck {
+    pub fn new(name: impl Into<String>) -> Self {
+        FlagCheck {
+            name: name.into(),
+            ready: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn set(&self, ready: bool) {
+        self.ready.store(ready, Ordering::SeqCst);
+    }
+}
+
+impl HealthCheck for FlagCheck {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn check(&self) -> BoxFuture<'_, Result<()>> {
+        let ready = self.ready.load(Ordering::SeqCst);
+        Box::pin(async move {
+            match ready {
+                true => Ok(()),
+                false => Err(Error::ErrorMessage("not ready".into())),
+            }
+        })
+    }
+}
+
+/// Checks that a bucket can be listed with the configured credentials.
+#[cfg(feature = "s3")]
+pub struct BucketCheck {
+    name: String,
+    bucket: toolcraft_s3_kit::BucketClient,
+}
+
+#[cfg(feature = "s3")]
+impl BucketCheck {
+    pub fn new(name: impl Into<String>, bucket: toolcraft_s3_kit::BucketClient) -> Self {
+        BucketCheck {
+            name: name.into(),
+            bucket,
+        }
+    }
+}
+
+#[cfg(feature = "s3")]
+impl HealthCheck for BucketCheck {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn check(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            // An unlikely prefix keeps the listing empty however large the bucket is.
+            self.bucket
+                .list_objects(Some(".healthz/"))
+                .await
+                .map(|_| ())
+                .map_err(|e| Error::ErrorMessage(e.to_string().into()))
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthStatus {
+    Ok,
+    Fail,
+    Timeout,
+    Draining,
+}
+
+/// Outcome of one check. Errors and latency are only logged, since `/readyz` is public.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CheckReport {
+    pub name: String,
+    pub status: HealthStatus,
+}
+
+/// Body of `/readyz`: the data of a `CommonResponse` when ready, of a `503` `CommonError`
+/// otherwise.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HealthReport {
+    pub status: HealthStatus,
+    pub checks: Vec<CheckReport>,
+}
+
+/// Liveness and readiness endpoints.
+///
+/// `/healthz` answers as long as the process serves requests. `/readyz` runs every check
+/// concurrently, each under its own timeout, and fails with `503` if any of them fails or once
+/// [`Health::start_draining`] was called.
+///
+/// ```rust,ignore
+/// let config_loaded = FlagCheck::new("config");
+/// let health = Health::new()
+///     .with_check(config_loaded.clone())
+///     .with_check_timeout(BucketCheck::new("uploads", bucket), Duration::from_secs(5));
+///
+/// let app = Router::new().merge(health.router()).nest("/api", api);
+/// start_with_shutdown(8080, app, health.graceful_shutdown(Duration::from_secs(10))).await?;
+/// ```
+#[derive(Clone)]
+pub struct Health {
+    checks: Vec<(Arc<dyn HealthCheck>, Duration)>,
+    timeout: Duration,
+    draining: Arc<AtomicBool>,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Health {
+    /// No checks; each added check times out after 2 seconds unless given its own timeout.
+    pub fn new() -> Self {
+        Health {
+            checks: Vec::new(),
+            timeout: Duration::from_secs(2),
+            draining: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn with_check(self, check: impl HealthCheck) -> Self {
+        let timeout = self.timeout;
+        self.with_check_timeout(check, timeout)
+    }
+
+    pub fn with_check_timeout(mut self, check: impl HealthCheck, timeout: Duration) -> Self {
+        self.checks.push((Arc::new(check), timeout));
+        self
+    }
+
+    /// Fail readiness from now on, so load balancers stop sending new requests.
+    pub fn start_draining(&self) {
+        self.draining.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    /// Resolve on Ctrl+C or `SIGTERM`, after draining for `grace`.
+    ///
+    /// Readiness fails as soon as the signal arrives; the server keeps serving during `grace`
+    /// so load balancers can notice and move traffic away before connections are closed.
+    pub fn graceful_shutdown(&self, grace: Duration) -> impl Future<Output = ()> + Send + 'static {
+        let health = self.clone();
+        async move {
+            crate::http_server::shutdown_signal().await;
+            tracing::info!(grace_secs = grace.as_secs(), "shutting down, draining");
+            health.start_draining();
+            tokio::time::sleep(grace).await;
+        }
+    }
+
+    /// Routes for `/healthz` and `/readyz`.
+    pub fn router<S>(&self) -> Router<S>
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        Router::new()
+            .route("/healthz", get(liveness))
+            .route("/readyz", get(readiness))
+            .with_state(self.clone())
+    }
+
+    /// Run all checks.
+    pub async fn report(&self) -> HealthReport {
+        let checks = join_all(
+            self.checks
+                .iter()
+                .map(|(check, timeout)| run_check(check.as_ref(), *timeout)),
+        )
+        .await;
+        let status = if self.is_draining() {
+            HealthStatus::Draining
+        } else if checks.iter().all(|c| c.status == HealthStatus::Ok) {
+            HealthStatus::Ok
+        } else {
+            HealthStatus::Fail
+        };
+        HealthReport { status, checks }
+    }
+}
+
+async fn run_check(check: &dyn HealthCheck, timeout: Duration) -> CheckReport {
+    let started = Instant::now();
+    let (status, error) = match tokio::time::timeout(timeout, check.check()).await {
+        Ok(Ok(())) => (HealthStatus::Ok, None),
+        Ok(Err(e)) => (HealthStatus::Fail, Some(e.to_string())),
+        Err(_) => (HealthStatus::Timeout, None),
+    };
+    if status != HealthStatus::Ok {
+        let latency_ms = started.elapsed().as_millis() as u64;
+        tracing::warn!(
+            check = check.name(),
+            ?status,
+            latency_ms,
+            error,
+            "health check failed"
+        );
+    }
+    CheckReport {
+        name: check.name().to_string(),
+        status,
+    }
+}
+
+async fn liveness() -> Response {
+    Json(HealthStatus::Ok.into_common_response()).into_response()
+}
+
+async fn readiness(State(health): State<Health>) -> Response {
+    let report = health.report().await;
+    if report.status == HealthStatus::Ok {
+        return Json(report.into_common_response()).into_response();
+    }
+    let message = match report.status {
+        HealthStatus::Draining => "shutting down",
+        _ => "not ready",
+    };
+    let error = CommonError::from((StatusCode::SERVICE_UNAVAILABLE.as_u16() as i16, message))
+        .with_data(report);
+    (StatusCode::SERVICE_UNAVAILABLE, Json(error)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::{Body, to_bytes},
+        http::Request,
+    };
+    use serde_json::{Value, json};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    async fn get(health: &Health, uri: &str) -> (StatusCode, Value) {
+        let response = health
+            .router::<()>()
+            .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_readiness_aggregates_checks() {
+        let config = FlagCheck::new("config");
+        let health = Health::new()
+            .with_check(config.clone())
+            .with_check(check_fn("db", || async { Ok(()) }))
+            .with_check_timeout(
+                check_fn("slow", || async {
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    Ok(())
+                }),
+                Duration::from_millis(20),
+            );
+
+        let (status, body) = get(&health, "/healthz").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"], "ok");
+
+        let (status, body) = get(&health, "/readyz").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["code"], 503);
+        assert_eq!(body["data"]["status"], "fail");
+        // Check errors are logged, not shown to callers.
+        assert_eq!(
+            body["data"]["checks"],
+            json!([
+                {"name": "config", "status": "fail"},
+                {"name": "db", "status": "ok"},
+                {"name": "slow", "status": "timeout"},
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_draining_fails_readiness() {
+        let config = FlagCheck::new("config");
+        let health = Health::new().with_check(config.clone());
+        config.set(true);
+
+        let (status, body) = get(&health, "/readyz").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["status"], "ok");
+
+        health.start_draining();
+        let (status, body) = get(&health, "/readyz").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["message"], "shutting down");
+        assert_eq!(body["data"]["status"], "draining");
+        assert_eq!(get(&health, "/healthz").await.0, StatusCode::OK);
+    }
+}
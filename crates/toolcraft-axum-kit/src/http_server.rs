@@ -1,13 +1,60 @@
+use std::{future::Future, net::SocketAddr};
+
 use axum::Router;
 
 use crate::error::Result;
 
 pub async fn start(port: u16, router: Router) -> Result<()> {
+    start_with_shutdown(port, router, std::future::pending()).await
+}
+
+/// Like [`start`], but stops accepting connections when `shutdown` resolves and returns once
+/// in-flight requests have finished.
+///
+/// Pass [`shutdown_signal`], or `Health::graceful_shutdown` to fail readiness before stopping.
+pub async fn start_with_shutdown<F>(port: u16, router: Router, shutdown: F) -> Result<()>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
     let addr = format!("0.0.0.0:{port}");
     let listener = tokio::net::TcpListener::bind(&addr).await?;
-    println!("HTTP Server is running on http://{addr}");
-    axum::serve(listener, router)
-        .await
-        .map_err(crate::error::Error::IoError)?;
+    tracing::info!("HTTP Server is running on http://{addr}");
+    axum::serve(
+        listener,
+        router.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown)
+    .await
+    .map_err(crate::error::Error::IoError)?;
     Ok(())
 }
+
+/// Resolve on Ctrl+C, or on `SIGTERM` on Unix.
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::warn!(error = %e, "failed to listen for Ctrl+C");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "failed to listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
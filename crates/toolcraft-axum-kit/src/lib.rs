@@ -1,9 +1,34 @@
 pub mod error;
+#[cfg(feature = "validation")]
+pub mod extract;
+pub mod health;
 pub mod http_server;
+#[cfg(feature = "metrics")]
+pub mod metrics;
 pub mod middleware;
+#[cfg(feature = "openapi")]
+pub mod openapi;
+pub mod pagination;
+pub mod problem;
 pub mod response;
+#[cfg(feature = "static-files")]
+pub mod static_files;
+pub mod streaming;
+#[cfg(feature = "test-util")]
+pub mod test_util;
+#[cfg(feature = "s3")]
+pub mod upload;
+#[cfg(feature = "ws")]
+pub mod ws;
 
-pub use http_server::start;
+#[cfg(feature = "validation")]
+pub use extract::{FieldError, ValidForm, ValidJson, ValidQuery};
+pub use health::{FlagCheck, Health, HealthCheck, check_fn};
+pub use http_server::{shutdown_signal, start, start_with_shutdown};
+pub use middleware::error_format::ErrorFormat;
+pub use pagination::{CursorCodec, PageLimits, PageQuery, Paginated};
+pub use problem::ProblemDetails;
 pub use response::{
     ApiError, CommonError, CommonOk, CommonResponse, Empty, IntoCommonResponse, ResponseResult,
 };
+pub use streaming::{LastEventId, SseEvent, ndjson, sse};
@@ -0,0 +1,236 @@
+use std::time::Instant;
+
+use axum::{
+    Router,
+    extract::{MatchedPath, Request, State},
+    http::{StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+
+use crate::error::{Error, Result};
+
+/// HTTP request metrics for the [`track_metrics`] middleware.
+///
+/// [`HttpMetrics::new`] registers into `prometheus::default_registry()`, which is also where the
+/// `metrics` features of `toolcraft-jwt` and `toolcraft-s3-kit` register their counters, so a
+/// single `/metrics` route exposes all of them. Application counters can be added through
+/// [`HttpMetrics::registry`].
+#[derive(Clone)]
+pub struct HttpMetrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    latency: HistogramVec,
+    in_flight: IntGaugeVec,
+}
+
+impl HttpMetrics {
+    /// Register the HTTP metrics in the process-wide default registry. Call once per process.
+    pub fn new() -> Result<Self> {
+        Self::with_registry(prometheus::default_registry().clone())
+    }
+
+    /// Register the HTTP metrics in a custom registry.
+    pub fn with_registry(registry: Registry) -> Result<Self> {
+        let requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "Total number of HTTP requests"),
+            &["method", "route", "status"],
+        )
+        .map_err(metrics_error)?;
+        let latency = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "HTTP request latency in seconds",
+            ),
+            &["method", "route", "status"],
+        )
+        .map_err(metrics_error)?;
+        let in_flight = IntGaugeVec::new(
+            Opts::new(
+                "http_requests_in_flight",
+                "Number of HTTP requests being served",
+            ),
+            &["method", "route"],
+        )
+        .map_err(metrics_error)?;
+
+        registry
+            .register(Box::new(requests.clone()))
+            .map_err(metrics_error)?;
+        registry
+            .register(Box::new(latency.clone()))
+            .map_err(metrics_error)?;
+        registry
+            .register(Box::new(in_flight.clone()))
+            .map_err(metrics_error)?;
+
+        Ok(HttpMetrics {
+            registry,
+            requests,
+            latency,
+            in_flight,
+        })
+    }
+
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    /// Render every metric in the registry in the Prometheus text format.
+    pub fn render(&self) -> Result<String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .map_err(metrics_error)?;
+        String::from_utf8(buffer).map_err(metrics_error)
+    }
+
+    /// A router serving [`HttpMetrics::render`] at `path`, usually `/metrics`.
+    pub fn router<S>(&self, path: &str) -> Router<S>
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        let metrics = self.clone();
+        Router::new().route(
+            path,
+            get(move || async move {
+                match metrics.render() {
+                    Ok(body) => {
+                        ([(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)], body).into_response()
+                    }
+                    Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+                }
+            }),
+        )
+    }
+}
+
+/// Record request count, latency and in-flight requests.
+///
+/// Labels are the method, the matched route template (`unmatched` for fallbacks, to keep
+/// cardinality bounded) and the status class (`2xx`, `4xx`, ...).
+///
+/// ```rust,ignore
+/// let metrics = HttpMetrics::new()?;
+/// let app = Router::new()
+///     .route("/users/{id}", get(get_user))
+///     .layer(middleware::from_fn_with_state(metrics.clone(), track_metrics))
+///     .merge(metrics.router("/metrics"));
+/// ```
+pub async fn track_metrics(
+    State(metrics): State<HttpMetrics>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let method = req.method().to_string();
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| String::from("unmatched"));
+
+    let in_flight = InFlight::new(metrics.in_flight.with_label_values(&[&method, &route]));
+    let start = Instant::now();
+    let response = next.run(req).await;
+    let elapsed = start.elapsed().as_secs_f64();
+    drop(in_flight);
+
+    let status = format!("{}xx", response.status().as_u16() / 100);
+    let labels = [method.as_str(), route.as_str(), status.as_str()];
+    metrics.requests.with_label_values(&labels).inc();
+    metrics.latency.with_label_values(&labels).observe(elapsed);
+    response
+}
+
+/// Holds the in-flight gauge up until dropped, including when the request future is cancelled.
+struct InFlight(IntGauge);
+
+impl InFlight {
+    fn new(gauge: IntGauge) -> Self {
+        gauge.inc();
+        InFlight(gauge)
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+fn metrics_error(e: impl std::fmt::Display) -> Error {
+    Error::ErrorMessage(format!("metrics error: {e}").into())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::{Body, to_bytes},
+        middleware,
+    };
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_metrics_are_recorded_and_rendered() {
+        let metrics = HttpMetrics::with_registry(Registry::new()).unwrap();
+        let app = Router::new()
+            .route("/users/{id}", get(|| async { "ok" }))
+            .layer(middleware::from_fn_with_state(
+                metrics.clone(),
+                track_metrics,
+            ))
+            .merge(metrics.router("/metrics"));
+
+        for uri in ["/users/1", "/users/2"] {
+            let response = app
+                .clone()
+                .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        let response = app
+            .oneshot(Request::get("/metrics").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            prometheus::TEXT_FORMAT
+        );
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(
+            body.contains(
+                r#"http_requests_total{method="GET",route="/users/{id}",status="2xx"} 2"#
+            ),
+            "{body}"
+        );
+        assert!(body.contains(r#"http_requests_in_flight{method="GET",route="/users/{id}"} 0"#));
+        assert!(body.contains("http_request_duration_seconds_bucket"));
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_request_leaves_in_flight() {
+        let metrics = HttpMetrics::with_registry(Registry::new()).unwrap();
+        let app = Router::new()
+            .route("/slow", get(std::future::pending::<&'static str>))
+            .layer(middleware::from_fn_with_state(
+                metrics.clone(),
+                track_metrics,
+            ));
+
+        let request = app.oneshot(Request::get("/slow").body(Body::empty()).unwrap());
+        let timed_out = tokio::time::timeout(std::time::Duration::from_millis(10), request).await;
+        assert!(timed_out.is_err());
+        let gauge = metrics.in_flight.with_label_values(&["GET", "/slow"]);
+        assert_eq!(gauge.get(), 0);
+    }
+}
@@ -0,0 +1,370 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex, MutexGuard},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    extract::Request,
+    http::{HeaderMap, HeaderName, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use toolcraft_jwt::AccessTokenVerifier;
+use uuid::Uuid;
+
+use crate::{
+    error::{Error, Result},
+    middleware::auth_mw::{AuthUser, Credential, request_token, run_as, verify_token},
+};
+
+pub const X_API_KEY: HeaderName = HeaderName::from_static("x-api-key");
+
+/// A stored API key. Only the SHA-256 of the key is kept; the key itself is shown once, by
+/// [`generate_api_key`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiKeyRecord {
+    /// Public part of the key (`<label>_<id>`), used for lookup and safe to display.
+    pub prefix: String,
+    /// Hex SHA-256 of the full key.
+    pub hash: String,
+    pub user_id: String,
+    pub scopes: Vec<String>,
+    /// Expiry as a unix timestamp in seconds; `None` never expires.
+    pub expires_at: Option<u64>,
+    /// Copied into `AuthUser.ext`.
+    pub ext: Option<Value>,
+}
+
+impl ApiKeyRecord {
+    pub fn with_scopes<I, S>(mut self, scopes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.scopes = scopes.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn with_expires_at(mut self, expires_at: u64) -> Self {
+        self.expires_at = Some(expires_at);
+        self
+    }
+
+    pub fn with_ext(mut self, ext: Value) -> Self {
+        self.ext = Some(ext);
+        self
+    }
+
+    fn is_expired(&self) -> bool {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+/// A freshly generated key: hand `key` to the client and store `record`.
+#[derive(Debug, Clone)]
+pub struct NewApiKey {
+    pub key: String,
+    pub record: ApiKeyRecord,
+}
+
+/// Generate a key for `user_id`, formatted `<label>_<id>_<secret>`.
+///
+/// `label` distinguishes kinds of keys, such as `live` and `test`, and must be ASCII
+/// alphanumeric. Scopes, expiry and `ext` are set on the returned record before storing it.
+///
+/// ```rust,ignore
+/// let NewApiKey { key, record } = generate_api_key("live", "user-1")?;
+/// store.insert(record.with_scopes(["orders:read"]))?;
+/// ```
+pub fn generate_api_key(label: &str, user_id: impl Into<String>) -> Result<NewApiKey> {
+    if label.is_empty() || !label.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(Error::ErrorMessage(
+            "api key label must be ASCII alphanumeric".into(),
+        ));
+    }
+    let id = Uuid::new_v4().simple().to_string();
+    let prefix = format!("{label}_{}", &id[.. 12]);
+    let key = format!("{prefix}_{}", Uuid::new_v4().simple());
+    let record = ApiKeyRecord {
+        prefix,
+        hash: hash_key(&key),
+        user_id: user_id.into(),
+        scopes: Vec::new(),
+        expires_at: None,
+        ext: None,
+    };
+    Ok(NewApiKey { key, record })
+}
+
+/// Storage for API key records, looked up by prefix.
+pub trait ApiKeyStore: Send + Sync + 'static {
+    fn find(&self, prefix: &str) -> impl Future<Output = Result<Option<ApiKeyRecord>>> + Send;
+}
+
+/// Process-local [`ApiKeyStore`].
+#[derive(Debug, Default)]
+pub struct MemoryApiKeyStore {
+    records: Mutex<HashMap<String, ApiKeyRecord>>,
+}
+
+impl MemoryApiKeyStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&self, record: ApiKeyRecord) -> Result<()> {
+        self.records()?.insert(record.prefix.clone(), record);
+        Ok(())
+    }
+
+    /// Remove the key with `prefix`, returning whether it existed.
+    pub fn revoke(&self, prefix: &str) -> Result<bool> {
+        Ok(self.records()?.remove(prefix).is_some())
+    }
+
+    fn records(&self) -> Result<MutexGuard<'_, HashMap<String, ApiKeyRecord>>> {
+        self.records
+            .lock()
+            .map_err(|_| Error::ErrorMessage("api key store poisoned".into()))
+    }
+}
+
+impl ApiKeyStore for MemoryApiKeyStore {
+    async fn find(&self, prefix: &str) -> Result<Option<ApiKeyRecord>> {
+        Ok(self.records()?.get(prefix).cloned())
+    }
+}
+
+/// Checks `X-Api-Key` values against an [`ApiKeyStore`].
+pub struct ApiKeyVerifier<S = MemoryApiKeyStore> {
+    store: Arc<S>,
+}
+
+impl<S> Clone for ApiKeyVerifier<S> {
+    fn clone(&self) -> Self {
+        ApiKeyVerifier {
+            store: self.store.clone(),
+        }
+    }
+}
+
+impl<S> ApiKeyVerifier<S>
+where
+    S: ApiKeyStore,
+{
+    pub fn new(store: Arc<S>) -> Self {
+        ApiKeyVerifier { store }
+    }
+
+    /// Resolve `key` to an [`AuthUser`]; unknown, mismatched and expired keys are `401`.
+    pub async fn verify(&self, key: &str) -> Result<AuthUser, StatusCode> {
+        let (prefix, _secret) = key.rsplit_once('_').ok_or(StatusCode::UNAUTHORIZED)?;
+        let record = match self.store.find(prefix).await {
+            Ok(Some(record)) => record,
+            Ok(None) => return Err(StatusCode::UNAUTHORIZED),
+            Err(e) => {
+                tracing::warn!(error = %e, "api key store failed");
+                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            }
+        };
+        if !constant_time_eq(hash_key(key).as_bytes(), record.hash.as_bytes())
+            || record.is_expired()
+        {
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+        Ok(AuthUser {
+            user_id: record.user_id,
+            ext: record.ext,
+            credential: Credential::ApiKey {
+                prefix: record.prefix,
+                scopes: record.scopes,
+            },
+        })
+    }
+}
+
+/// Like [`auth`](crate::middleware::auth_mw::auth), but also accepts an `X-Api-Key`.
+///
+/// Requests with an `X-Api-Key` header are checked by the `Arc<ApiKeyVerifier<S>>` extension,
+/// anything else needs a bearer token for the `Arc<T>` extension. Both produce an `AuthUser`;
+/// `credential` tells them apart.
+///
+/// ```rust,ignore
+/// let app = Router::new()
+///     .route("/orders", get(list_orders))
+///     .layer(middleware::from_fn(auth_or_api_key::<Jwt, MemoryApiKeyStore>))
+///     .layer(Extension(jwt_verifier))
+///     .layer(Extension(Arc::new(ApiKeyVerifier::new(key_store))));
+/// ```
+pub async fn auth_or_api_key<T, S>(req: Request, next: Next) -> Result<Response, StatusCode>
+where
+    T: AccessTokenVerifier + 'static,
+    S: ApiKeyStore,
+{
+    let auth_user = match api_key(req.headers())? {
+        Some(key) => {
+            let verifier = req
+                .extensions()
+                .get::<Arc<ApiKeyVerifier<S>>>()
+                .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+            verifier.verify(key).await?
+        }
+        None => {
+            let token = request_token(&req)?;
+            let jwt = req
+                .extensions()
+                .get::<Arc<T>>()
+                .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+            verify_token(jwt.as_ref(), &token)?
+        }
+    };
+    Ok(run_as(auth_user, req, next).await)
+}
+
+fn api_key(headers: &HeaderMap) -> Result<Option<&str>, StatusCode> {
+    match headers.get(&X_API_KEY) {
+        Some(value) => {
+            let key = value.to_str().map_err(|_| StatusCode::UNAUTHORIZED)?.trim();
+            if key.is_empty() {
+                return Err(StatusCode::UNAUTHORIZED);
+            }
+            Ok(Some(key))
+        }
+        None => Ok(None),
+    }
+}
+
+fn hash_key(key: &str) -> String {
+    Sha256::digest(key.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        Extension, Router,
+        body::{Body, to_bytes},
+        http::header,
+        middleware::from_fn,
+        routing::get,
+    };
+    use toolcraft_jwt::Claims;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    struct StaticVerifier;
+
+    impl AccessTokenVerifier for StaticVerifier {
+        fn validate_access_token(&self, token: &str) -> toolcraft_jwt::Result<Claims> {
+            match token {
+                "good" => Ok(Claims {
+                    iss: String::new(),
+                    aud: String::new(),
+                    sub: String::from("jwt-user"),
+                    exp: 0,
+                    iat: 0,
+                    ext: None,
+                }),
+                _ => Err(toolcraft_jwt::error::Error::AuthError("bad token".into())),
+            }
+        }
+    }
+
+    fn app(store: Arc<MemoryApiKeyStore>) -> Router {
+        Router::new()
+            .route(
+                "/me",
+                get(|Extension(user): Extension<AuthUser>| async move {
+                    let kind = match user.credential {
+                        Credential::Jwt => "jwt",
+                        Credential::ApiKey { .. } => "api-key",
+                    };
+                    format!("{} via {kind}", user.user_id)
+                }),
+            )
+            .layer(from_fn(
+                auth_or_api_key::<StaticVerifier, MemoryApiKeyStore>,
+            ))
+            .layer(Extension(Arc::new(StaticVerifier)))
+            .layer(Extension(Arc::new(ApiKeyVerifier::new(store))))
+    }
+
+    async fn send(app: Router, header: (HeaderName, &str)) -> (StatusCode, String) {
+        let request = Request::get("/me")
+            .header(header.0, header.1)
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_api_key_and_jwt_produce_auth_user() {
+        let store = Arc::new(MemoryApiKeyStore::new());
+        let NewApiKey { key, record } = generate_api_key("live", "machine-1").unwrap();
+        assert!(key.starts_with(&format!("{}_", record.prefix)));
+        assert!(!record.hash.contains(&key));
+        store.insert(record.with_scopes(["orders:read"])).unwrap();
+
+        let app = app(store);
+        assert_eq!(
+            send(app.clone(), (X_API_KEY, &key)).await,
+            (StatusCode::OK, String::from("machine-1 via api-key"))
+        );
+        assert_eq!(
+            send(app, (header::AUTHORIZATION, "Bearer good")).await,
+            (StatusCode::OK, String::from("jwt-user via jwt"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rejects_wrong_expired_and_revoked_keys() {
+        let store = Arc::new(MemoryApiKeyStore::new());
+        let valid = generate_api_key("live", "machine-1").unwrap();
+        let expired = generate_api_key("live", "machine-2").unwrap();
+        store.insert(valid.record.clone()).unwrap();
+        store.insert(expired.record.with_expires_at(1)).unwrap();
+        let app = app(store.clone());
+
+        let forged = format!("{}_{}", valid.record.prefix, "0".repeat(32));
+        for key in [forged.as_str(), &expired.key, "garbage"] {
+            let (status, _) = send(app.clone(), (X_API_KEY, key)).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{key}");
+        }
+
+        assert!(store.revoke(&valid.record.prefix).unwrap());
+        let (status, _) = send(app, (X_API_KEY, &valid.key)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_scopes_on_auth_user() {
+        let store = Arc::new(MemoryApiKeyStore::new());
+        let new = generate_api_key("test", "machine-1").unwrap();
+        store
+            .insert(new.record.with_scopes(["orders:read"]))
+            .unwrap();
+        let user = ApiKeyVerifier::new(store).verify(&new.key).await.unwrap();
+        assert!(user.has_api_key_scope("orders:read"));
+        assert!(!user.has_api_key_scope("orders:write"));
+        assert!(generate_api_key("bad_label", "u").is_err());
+    }
+}
@@ -0,0 +1,457 @@
+use std::{
+    future::Future,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    body::{Body, to_bytes},
+    extract::{FromRequestParts, MatchedPath, RawPathParams, Request, State},
+    http::{Method, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http_body_util::LengthLimitError;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::{fs::File, io::AsyncWriteExt};
+
+use crate::{
+    error::{Error, Result},
+    middleware::request_id::RequestId,
+    response::api_error,
+};
+
+/// Replacement for redacted values.
+pub const REDACTED: &str = "[REDACTED]";
+
+/// One audited request.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Unix time in milliseconds when the request completed.
+    pub timestamp_ms: u64,
+    /// `AuthUser.user_id`, `None` for anonymous requests.
+    pub actor: Option<String>,
+    pub method: String,
+    /// Matched route template, such as `/orders/{id}`.
+    pub route: String,
+    pub path: String,
+    /// Path parameter identifying the changed resource.
+    pub resource_id: Option<String>,
+    pub status: u16,
+    pub request_id: Option<String>,
+    /// JSON request body with sensitive fields redacted.
+    pub body: Option<Value>,
+}
+
+/// Destination of audit entries.
+pub trait AuditSink: Send + Sync + 'static {
+    fn record(&self, entry: AuditEntry) -> impl Future<Output = Result<()>> + Send;
+}
+
+/// Keeps entries in memory, for tests and debugging.
+#[derive(Debug, Default)]
+pub struct MemoryAuditSink {
+    entries: Mutex<Vec<AuditEntry>>,
+}
+
+impl MemoryAuditSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn entries(&self) -> Vec<AuditEntry> {
+        self.entries
+            .lock()
+            .map(|entries| entries.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl AuditSink for MemoryAuditSink {
+    async fn record(&self, entry: AuditEntry) -> Result<()> {
+        self.entries
+            .lock()
+            .map_err(|_| Error::ErrorMessage("audit sink poisoned".into()))?
+            .push(entry);
+        Ok(())
+    }
+}
+
+/// Appends entries to a file, one JSON object per line.
+#[derive(Debug)]
+pub struct JsonLinesAuditSink {
+    file: tokio::sync::Mutex<File>,
+}
+
+impl JsonLinesAuditSink {
+    /// Open `path` for appending, creating it if needed.
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        Ok(JsonLinesAuditSink {
+            file: tokio::sync::Mutex::new(file),
+        })
+    }
+}
+
+impl AuditSink for JsonLinesAuditSink {
+    async fn record(&self, entry: AuditEntry) -> Result<()> {
+        let mut line =
+            serde_json::to_vec(&entry).map_err(|e| Error::ErrorMessage(e.to_string().into()))?;
+        line.push(b'\n');
+        let mut file = self.file.lock().await;
+        file.write_all(&line).await?;
+        file.flush().await?;
+        Ok(())
+    }
+}
+
+/// Settings for the [`audit`] middleware.
+pub struct Audit<S> {
+    sink: Arc<S>,
+    redacted_fields: Vec<String>,
+    resource_param: String,
+    max_body: usize,
+}
+
+impl<S> Clone for Audit<S> {
+    fn clone(&self) -> Self {
+        Audit {
+            sink: self.sink.clone(),
+            redacted_fields: self.redacted_fields.clone(),
+            resource_param: self.resource_param.clone(),
+            max_body: self.max_body,
+        }
+    }
+}
+
+impl<S> Audit<S>
+where
+    S: AuditSink,
+{
+    /// Redacts `password`, `token`, `secret` and `api_key` fields, reads the resource id from the
+    /// `id` path parameter and records JSON bodies up to 64 KiB.
+    pub fn new(sink: Arc<S>) -> Self {
+        Audit {
+            sink,
+            redacted_fields: ["password", "token", "secret", "api_key"]
+                .map(String::from)
+                .to_vec(),
+            resource_param: String::from("id"),
+            max_body: 64 * 1024,
+        }
+    }
+
+    /// Replace the redacted field names. Matching is case-insensitive, at any depth.
+    pub fn with_redacted_fields<I, T>(mut self, fields: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.redacted_fields = fields.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Path parameter holding the resource id; routes without it are recorded without one.
+    pub fn with_resource_param(mut self, param: impl Into<String>) -> Self {
+        self.resource_param = param.into();
+        self
+    }
+
+    /// Largest request body recorded; larger or unsized bodies are passed on without recording.
+    pub fn with_max_body(mut self, max_body: usize) -> Self {
+        self.max_body = max_body;
+        self
+    }
+
+    fn redact(&self, value: &mut Value) {
+        match value {
+            Value::Object(map) => {
+                for (key, value) in map.iter_mut() {
+                    if self
+                        .redacted_fields
+                        .iter()
+                        .any(|field| field.eq_ignore_ascii_case(key))
+                    {
+                        *value = Value::String(REDACTED.to_string());
+                    } else {
+                        self.redact(value);
+                    }
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|item| self.redact(item)),
+            _ => {}
+        }
+    }
+}
+
+/// Record every request other than `GET`, `HEAD` and `OPTIONS` to the [`AuditSink`].
+///
+/// Add it with `Router::layer` so the matched route and path parameters are known, and outside
+/// the auth middleware, which exposes the `AuthUser` on the response. Sink failures are logged and
+/// never fail the request.
+///
+/// ```rust,ignore
+/// let sink = Arc::new(JsonLinesAuditSink::open("/var/log/app/audit.jsonl").await?);
+/// let app = Router::new()
+///     .route("/orders/{id}", patch(update_order).delete(delete_order))
+///     .layer(middleware::from_fn(auth::<Jwt>))
+///     .layer(Extension(jwt_verifier))
+///     .layer(middleware::from_fn_with_state(Audit::new(sink), audit::<JsonLinesAuditSink>));
+/// ```
+pub async fn audit<S>(State(cfg): State<Audit<S>>, req: Request, next: Next) -> Response
+where
+    S: AuditSink,
+{
+    if matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        return next.run(req).await;
+    }
+
+    let (mut parts, body) = req.into_parts();
+    let route = parts
+        .extensions
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| parts.uri.path().to_string());
+    let resource_id = RawPathParams::from_request_parts(&mut parts, &())
+        .await
+        .ok()
+        .and_then(|params| {
+            params
+                .iter()
+                .find(|(name, _)| *name == cfg.resource_param)
+                .map(|(_, value)| value.to_string())
+        });
+    let mut entry = AuditEntry {
+        timestamp_ms: 0,
+        actor: None,
+        method: parts.method.to_string(),
+        route,
+        path: parts.uri.path().to_string(),
+        resource_id,
+        status: 0,
+        request_id: parts.extensions.get::<RequestId>().map(|id| id.0.clone()),
+        body: None,
+    };
+
+    let is_json = parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    let length = parts
+        .headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    let body = match length {
+        Some(length) if is_json && length <= cfg.max_body => {
+            match to_bytes(body, length).await {
+                Ok(bytes) => {
+                    entry.body = serde_json::from_slice(&bytes).ok().map(|mut value| {
+                        cfg.redact(&mut value);
+                        value
+                    });
+                    Ok(Body::from(bytes))
+                }
+                // The handler must not run on a truncated body.
+                Err(e) if is_length_limit(&e) => Err(api_error(
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    "request body exceeds its Content-Length",
+                )),
+                Err(e) => {
+                    tracing::warn!(error = %e, "failed to read request body");
+                    Err(api_error(
+                        StatusCode::BAD_REQUEST,
+                        "failed to read request body",
+                    ))
+                }
+            }
+        }
+        _ => Ok(body),
+    };
+
+    #[cfg(feature = "jwt")]
+    let request_actor = parts
+        .extensions
+        .get::<crate::middleware::auth_mw::AuthUser>()
+        .map(|user| user.user_id.clone());
+    let response = match body {
+        Ok(body) => next.run(Request::from_parts(parts, body)).await,
+        Err(error) => error.into_response(),
+    };
+
+    #[cfg(feature = "jwt")]
+    {
+        entry.actor = response
+            .extensions()
+            .get::<crate::middleware::auth_mw::AuthUser>()
+            .map(|user| user.user_id.clone())
+            .or(request_actor);
+    }
+    entry.status = response.status().as_u16();
+    entry.timestamp_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    if let Err(e) = cfg.sink.record(entry).await {
+        tracing::warn!(error = %e, "failed to record audit entry");
+    }
+    response
+}
+
+fn is_length_limit(error: &axum::Error) -> bool {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(error);
+    while let Some(e) = source {
+        if e.is::<LengthLimitError>() {
+            return true;
+        }
+        source = e.source();
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        Json, Router, middleware,
+        routing::{get, patch},
+    };
+    use serde_json::json;
+    use tower::ServiceExt;
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn app<S: AuditSink>(sink: Arc<S>) -> Router {
+        Router::new()
+            .route(
+                "/orgs/{org}/orders/{id}",
+                patch(|Json(body): Json<Value>| async move { Json(body) })
+                    .get(|| async { "order" }),
+            )
+            .route(
+                "/orders",
+                get(|| async { "orders" }).post(|| async { StatusCode::CREATED }),
+            )
+            .route(
+                "/orgs/{org}/orders",
+                patch(|Json(body): Json<Value>| async move { Json(body) }),
+            )
+            .layer(middleware::from_fn_with_state(Audit::new(sink), audit::<S>))
+    }
+
+    fn patch_request() -> Request {
+        let body = json!({
+            "status": "shipped",
+            "payment": {"card": "4242", "Token": "tok_1"},
+            "password": "hunter2"
+        });
+        Request::patch("/orgs/acme/orders/42")
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::CONTENT_LENGTH, body.to_string().len())
+            .extension(RequestId(String::from("req-1")))
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_records_mutations_with_redaction() {
+        let sink = Arc::new(MemoryAuditSink::new());
+        let app = app(sink.clone());
+
+        let response = app.clone().oneshot(patch_request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let echoed: Value =
+            serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap())
+                .unwrap();
+        assert_eq!(echoed["password"], "hunter2");
+
+        app.clone()
+            .oneshot(
+                Request::get("/orgs/acme/orders/42")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        app.oneshot(Request::post("/orders").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        let entries = sink.entries();
+        assert_eq!(entries.len(), 2);
+        let entry = &entries[0];
+        assert_eq!(entry.method, "PATCH");
+        assert_eq!(entry.route, "/orgs/{org}/orders/{id}");
+        assert_eq!(entry.resource_id.as_deref(), Some("42"));
+        assert_eq!(entry.status, 200);
+        assert_eq!(entry.request_id.as_deref(), Some("req-1"));
+        assert_eq!(
+            entry.body,
+            Some(json!({
+                "status": "shipped",
+                "payment": {"card": "4242", "Token": REDACTED},
+                "password": REDACTED
+            }))
+        );
+        assert!(entry.timestamp_ms > 0);
+        assert_eq!(
+            (entries[1].status, entries[1].resource_id.as_deref()),
+            (201, None)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_json_lines_sink() {
+        let path = std::env::temp_dir().join(format!("toolcraft-audit-{}.jsonl", Uuid::new_v4()));
+        let sink = Arc::new(JsonLinesAuditSink::open(&path).await.unwrap());
+        let app = app(sink);
+        app.clone().oneshot(patch_request()).await.unwrap();
+        app.oneshot(patch_request()).await.unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let entries: Vec<AuditEntry> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].resource_id.as_deref(), Some("42"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_resource_id_only_from_configured_param() {
+        let sink = Arc::new(MemoryAuditSink::new());
+        let request = Request::patch("/orgs/acme/orders")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from("{}"))
+            .unwrap();
+        app(sink.clone()).oneshot(request).await.unwrap();
+        let entries = sink.entries();
+        assert_eq!(entries[0].route, "/orgs/{org}/orders");
+        assert_eq!(entries[0].resource_id, None);
+    }
+
+    #[tokio::test]
+    async fn test_body_longer_than_content_length_is_rejected() {
+        let sink = Arc::new(MemoryAuditSink::new());
+        let request = Request::patch("/orgs/acme/orders/42")
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::CONTENT_LENGTH, "2")
+            .body(Body::from(r#"{"status": "shipped"}"#))
+            .unwrap();
+        let response = app(sink.clone()).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let body: Value =
+            serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap())
+                .unwrap();
+        assert_eq!(body["code"], 413);
+        assert_eq!(sink.entries()[0].status, 413);
+    }
+}
@@ -9,32 +9,90 @@ use axum::{
 use serde_json::Value;
 use toolcraft_jwt::AccessTokenVerifier;
 
+use crate::middleware::csrf::read_cookie;
+
 #[derive(Debug, Clone)]
 pub struct AuthUser {
     pub user_id: String,
     pub ext: Option<Value>,
+    pub credential: Credential,
+}
+
+/// How an [`AuthUser`] authenticated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Credential {
+    /// A bearer access token.
+    Jwt,
+    /// An `X-Api-Key`, identified by its public prefix.
+    ApiKey { prefix: String, scopes: Vec<String> },
+}
+
+impl AuthUser {
+    /// Whether the user authenticated with an API key granting `scope`. Always `false` for JWTs.
+    pub fn has_api_key_scope(&self, scope: &str) -> bool {
+        match &self.credential {
+            Credential::ApiKey { scopes, .. } => scopes.iter().any(|s| s == scope),
+            Credential::Jwt => false,
+        }
+    }
 }
 
-pub async fn auth<T>(mut req: Request, next: Next) -> Result<Response, StatusCode>
+/// Name of the cookie carrying the access token, for browser sessions.
+///
+/// When this extension is present, [`auth`] falls back to the cookie for requests without an
+/// `Authorization` header. Protect such routes with the [`csrf`](crate::middleware::csrf) layer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthCookie(pub String);
+
+pub async fn auth<T>(req: Request, next: Next) -> Result<Response, StatusCode>
 where
     T: AccessTokenVerifier + 'static,
 {
-    let headers = req.headers();
-    let token = parse_token(headers)?;
+    let token = request_token(&req)?;
     let jwt = req
         .extensions()
         .get::<Arc<T>>()
         .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
-    let claims = jwt
-        .validate_access_token(&token)
+    let auth_user = verify_token(jwt.as_ref(), &token)?;
+    Ok(run_as(auth_user, req, next).await)
+}
+
+/// Run the rest of the stack as `auth_user`.
+pub(crate) async fn run_as(auth_user: AuthUser, mut req: Request, next: Next) -> Response {
+    req.extensions_mut().insert(auth_user.clone());
+
+    // Also expose the user on the response, for outer layers such as the access log.
+    let mut response = next.run(req).await;
+    response.extensions_mut().insert(auth_user);
+    response
+}
+
+/// Validate `token` and turn its claims into an [`AuthUser`].
+pub(crate) fn verify_token<T>(verifier: &T, token: &str) -> Result<AuthUser, StatusCode>
+where
+    T: AccessTokenVerifier + ?Sized,
+{
+    let claims = verifier
+        .validate_access_token(token)
         .map_err(|_| StatusCode::UNAUTHORIZED)?;
-    let auth_user = AuthUser {
+    Ok(AuthUser {
         user_id: claims.sub,
         ext: claims.ext,
-    };
-    req.extensions_mut().insert(auth_user);
+        credential: Credential::Jwt,
+    })
+}
 
-    Ok(next.run(req).await)
+/// The bearer token, or the [`AuthCookie`] when there is no `Authorization` header.
+pub(crate) fn request_token(req: &Request) -> Result<String, StatusCode> {
+    if !req.headers().contains_key(header::AUTHORIZATION)
+        && let Some(AuthCookie(name)) = req.extensions().get::<AuthCookie>()
+    {
+        return read_cookie(req.headers(), name)
+            .filter(|token| !token.is_empty())
+            .map(str::to_string)
+            .ok_or(StatusCode::UNAUTHORIZED);
+    }
+    parse_token(req.headers())
 }
 
 fn parse_token(headers: &HeaderMap) -> Result<String, StatusCode> {
@@ -1,8 +1,268 @@
-use tower_http::cors::{Any, CorsLayer};
+use std::time::Duration;
 
+use axum::http::{HeaderName, HeaderValue, Method, request::Parts};
+use serde::Deserialize;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+use crate::error::{Error, Result};
+
+/// Permissive preset for local development: any origin, method and header.
 pub fn create_cors() -> CorsLayer {
     CorsLayer::new()
         .allow_methods(Any) // 允许任意 HTTP 方法
         .allow_origin(Any) // 允许任意来源
         .allow_headers(Any) // 允许任意请求头，包括 Content-Type
 }
+
+/// CORS policy loaded from settings, turned into a layer with [`CorsCfg::build`].
+///
+/// Origins are exact (`https://app.example.com`), wildcard subdomains
+/// (`https://*.example.com`, which does not match `https://example.com` itself) or `*`.
+///
+/// ```toml
+/// [cors]
+/// allowed_origins = ["https://app.example.com", "https://*.example.com"]
+/// allow_credentials = true
+/// exposed_headers = ["x-request-id"]
+/// max_age_secs = 600
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct CorsCfg {
+    pub allowed_origins: Vec<String>,
+    #[serde(default = "default_methods")]
+    pub allowed_methods: Vec<String>,
+    #[serde(default = "default_headers")]
+    pub allowed_headers: Vec<String>,
+    #[serde(default)]
+    pub allow_credentials: bool,
+    #[serde(default)]
+    pub exposed_headers: Vec<String>,
+    #[serde(default)]
+    pub max_age_secs: Option<u64>,
+}
+
+fn default_methods() -> Vec<String> {
+    ["GET", "POST", "PUT", "PATCH", "DELETE"]
+        .map(String::from)
+        .to_vec()
+}
+
+fn default_headers() -> Vec<String> {
+    ["authorization", "content-type"].map(String::from).to_vec()
+}
+
+impl CorsCfg {
+    pub fn new<I, T>(allowed_origins: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        CorsCfg {
+            allowed_origins: allowed_origins.into_iter().map(Into::into).collect(),
+            allowed_methods: default_methods(),
+            allowed_headers: default_headers(),
+            allow_credentials: false,
+            exposed_headers: Vec::new(),
+            max_age_secs: None,
+        }
+    }
+
+    /// Build the layer, rejecting configurations browsers would refuse, such as credentials
+    /// combined with `*`.
+    pub fn build(&self) -> Result<CorsLayer> {
+        if self.allowed_origins.is_empty() {
+            return Err(cors_error("allowed_origins must not be empty"));
+        }
+        let lists = [
+            ("allowed_origins", &self.allowed_origins),
+            ("allowed_methods", &self.allowed_methods),
+            ("allowed_headers", &self.allowed_headers),
+            ("exposed_headers", &self.exposed_headers),
+        ];
+        for (name, list) in lists {
+            if is_wildcard(list) && list.len() > 1 {
+                return Err(cors_error(&format!(
+                    "{name}: `*` cannot be combined with other values"
+                )));
+            }
+            if self.allow_credentials && is_wildcard(list) {
+                return Err(cors_error(&format!(
+                    "{name}: `*` is not allowed with allow_credentials"
+                )));
+            }
+        }
+
+        let mut layer = CorsLayer::new()
+            .allow_origin(self.allow_origin()?)
+            .allow_credentials(self.allow_credentials);
+        layer = if is_wildcard(&self.allowed_methods) {
+            layer.allow_methods(Any)
+        } else {
+            layer.allow_methods(parse_all::<Method>(&self.allowed_methods, "method")?)
+        };
+        layer = if is_wildcard(&self.allowed_headers) {
+            layer.allow_headers(Any)
+        } else {
+            layer.allow_headers(parse_all::<HeaderName>(&self.allowed_headers, "header")?)
+        };
+        layer = if is_wildcard(&self.exposed_headers) {
+            layer.expose_headers(Any)
+        } else {
+            layer.expose_headers(parse_all::<HeaderName>(&self.exposed_headers, "header")?)
+        };
+        if let Some(max_age) = self.max_age_secs {
+            layer = layer.max_age(Duration::from_secs(max_age));
+        }
+        Ok(layer)
+    }
+
+    fn allow_origin(&self) -> Result<AllowOrigin> {
+        if is_wildcard(&self.allowed_origins) {
+            return Ok(AllowOrigin::any());
+        }
+        let mut exact = Vec::new();
+        let mut patterns = Vec::new();
+        for origin in &self.allowed_origins {
+            match origin.split_once("*.") {
+                Some((scheme, domain)) => {
+                    if !scheme.ends_with("://") || domain.is_empty() || domain.contains('*') {
+                        return Err(cors_error(&format!("invalid origin pattern: {origin}")));
+                    }
+                    patterns.push((scheme.to_string(), format!(".{domain}")));
+                }
+                None => exact.push(
+                    HeaderValue::from_str(origin)
+                        .map_err(|_| cors_error(&format!("invalid origin: {origin}")))?,
+                ),
+            }
+        }
+        if patterns.is_empty() {
+            return Ok(AllowOrigin::list(exact));
+        }
+        Ok(AllowOrigin::predicate(
+            move |origin: &HeaderValue, _: &Parts| {
+                exact.contains(origin)
+                    || origin.to_str().is_ok_and(|origin| {
+                        patterns
+                            .iter()
+                            .any(|(scheme, suffix)| matches_pattern(origin, scheme, suffix))
+                    })
+            },
+        ))
+    }
+}
+
+fn matches_pattern(origin: &str, scheme: &str, suffix: &str) -> bool {
+    origin
+        .strip_prefix(scheme)
+        .and_then(|rest| rest.strip_suffix(suffix))
+        .is_some_and(|subdomain| {
+            !subdomain.is_empty()
+                && subdomain.split('.').all(|label| {
+                    !label.is_empty()
+                        && label
+                            .bytes()
+                            .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+                })
+        })
+}
+
+fn is_wildcard(list: &[String]) -> bool {
+    list.iter().any(|v| v == "*")
+}
+
+fn parse_all<T>(values: &[String], kind: &str) -> Result<Vec<T>>
+where
+    T: std::str::FromStr,
+{
+    values
+        .iter()
+        .map(|v| {
+            v.parse()
+                .map_err(|_| cors_error(&format!("invalid {kind}: {v}")))
+        })
+        .collect()
+}
+
+fn cors_error(message: &str) -> Error {
+    Error::ErrorMessage(format!("invalid CORS config: {message}").into())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        Router,
+        body::Body,
+        http::{Request, header},
+        routing::get,
+    };
+    use tower::ServiceExt;
+
+    use super::*;
+
+    async fn allowed_origin(cfg: &CorsCfg, origin: &str) -> Option<HeaderValue> {
+        let app = Router::new()
+            .route("/", get(|| async { "ok" }))
+            .layer(cfg.build().unwrap());
+        let request = Request::get("/")
+            .header(header::ORIGIN, origin)
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        response
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .cloned()
+    }
+
+    #[tokio::test]
+    async fn test_exact_and_wildcard_subdomain_origins() {
+        let cfg = CorsCfg::new(["https://app.example.com", "https://*.example.org"]);
+        assert!(
+            allowed_origin(&cfg, "https://app.example.com")
+                .await
+                .is_some()
+        );
+        assert!(
+            allowed_origin(&cfg, "https://a.b.example.org")
+                .await
+                .is_some()
+        );
+        assert!(allowed_origin(&cfg, "https://example.org").await.is_none());
+        assert!(allowed_origin(&cfg, "http://a.example.org").await.is_none());
+        assert!(
+            allowed_origin(&cfg, "https://evil-example.org")
+                .await
+                .is_none()
+        );
+        assert!(allowed_origin(&cfg, "https://other.com").await.is_none());
+    }
+
+    #[test]
+    fn test_invalid_combinations_are_rejected() {
+        let mut cfg = CorsCfg::new(["*"]);
+        assert!(cfg.build().is_ok());
+        cfg.allow_credentials = true;
+        assert!(cfg.build().is_err());
+
+        let mut cfg = CorsCfg::new(["https://app.example.com"]);
+        cfg.allow_credentials = true;
+        cfg.allowed_headers = vec!["*".to_string()];
+        assert!(cfg.build().is_err());
+
+        assert!(
+            CorsCfg::new(["*", "https://app.example.com"])
+                .build()
+                .is_err()
+        );
+        assert!(CorsCfg::new(Vec::<String>::new()).build().is_err());
+        assert!(CorsCfg::new(["*.example.com"]).build().is_err());
+    }
+
+    #[test]
+    fn test_deserialize_defaults() {
+        let cfg: CorsCfg =
+            serde_json::from_str(r#"{"allowed_origins": ["https://app.example.com"]}"#).unwrap();
+        assert_eq!(cfg, CorsCfg::new(["https://app.example.com"]));
+    }
+}
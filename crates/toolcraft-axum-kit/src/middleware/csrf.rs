@@ -0,0 +1,444 @@
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+use hmac::{Hmac, KeyInit, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::{
+    error::{Error, Result},
+    response::api_error,
+};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The CSRF token of the current request, for embedding in server-rendered forms.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsrfToken(pub String);
+
+/// CSRF settings.
+///
+/// ```toml
+/// [csrf]
+/// secret = "change-me"
+/// auth_cookie = "access_token"
+/// exempt_paths = ["/webhooks/*"]
+/// allowed_origins = ["https://app.example.com"]
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct CsrfCfg {
+    /// Key signing the tokens, so only tokens issued by the server are accepted.
+    pub secret: String,
+    #[serde(default = "default_cookie_name")]
+    pub cookie_name: String,
+    #[serde(default = "default_header_name")]
+    pub header_name: String,
+    /// Only check requests carrying this cookie, usually the
+    /// [`AuthCookie`](crate::middleware::auth_mw::AuthCookie). Requests authenticated with an
+    /// `Authorization` header cannot be forged cross-site. Tokens are signed together with the
+    /// cookie's value, so a token only works for the session it was issued to. `None` checks every
+    /// request, with tokens that are not tied to a session.
+    #[serde(default)]
+    pub auth_cookie: Option<String>,
+    /// Paths that are never checked, exact or ending in `/*` for a prefix.
+    #[serde(default)]
+    pub exempt_paths: Vec<String>,
+    /// Origins allowed to send unsafe requests. Empty means the request's own `Host`.
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    /// Check `Origin`, or `Referer` when there is none, on unsafe requests.
+    #[serde(default = "default_true")]
+    pub check_origin: bool,
+    #[serde(default = "default_true")]
+    pub cookie_secure: bool,
+}
+
+fn default_cookie_name() -> String {
+    String::from("csrf_token")
+}
+
+fn default_header_name() -> String {
+    String::from("x-csrf-token")
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl CsrfCfg {
+    pub fn new(secret: impl Into<String>) -> Self {
+        CsrfCfg {
+            secret: secret.into(),
+            cookie_name: default_cookie_name(),
+            header_name: default_header_name(),
+            auth_cookie: None,
+            exempt_paths: Vec::new(),
+            allowed_origins: Vec::new(),
+            check_origin: true,
+            cookie_secure: true,
+        }
+    }
+}
+
+/// State of the [`csrf`] middleware, built from a [`CsrfCfg`].
+#[derive(Debug, Clone)]
+pub struct Csrf {
+    cfg: CsrfCfg,
+    header: HeaderName,
+}
+
+impl Csrf {
+    pub fn new(cfg: CsrfCfg) -> Result<Self> {
+        if cfg.secret.is_empty() {
+            return Err(Error::ErrorMessage("csrf secret must not be empty".into()));
+        }
+        let header = HeaderName::try_from(cfg.header_name.as_str())
+            .map_err(|_| Error::ErrorMessage("invalid csrf header_name".into()))?;
+        Ok(Csrf { cfg, header })
+    }
+
+    /// A new token for `session`, the auth cookie's value.
+    fn issue(&self, session: &str) -> String {
+        let nonce = Uuid::new_v4().simple().to_string();
+        let signature = URL_SAFE_NO_PAD.encode(self.mac(&nonce, session).finalize().into_bytes());
+        format!("{nonce}.{signature}")
+    }
+
+    fn is_valid(&self, token: &str, session: &str) -> bool {
+        let Some((nonce, signature)) = token.split_once('.') else {
+            return false;
+        };
+        let Ok(signature) = URL_SAFE_NO_PAD.decode(signature) else {
+            return false;
+        };
+        self.mac(nonce, session).verify_slice(&signature).is_ok()
+    }
+
+    fn mac(&self, nonce: &str, session: &str) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(self.cfg.secret.as_bytes())
+            .expect("HMAC can take key of any size");
+        mac.update(nonce.as_bytes());
+        mac.update(b"\0");
+        mac.update(session.as_bytes());
+        mac
+    }
+
+    /// Value of the auth cookie the tokens are bound to.
+    fn session<'a>(&self, headers: &'a HeaderMap) -> Option<&'a str> {
+        read_cookie(headers, self.cfg.auth_cookie.as_deref()?)
+    }
+
+    fn is_exempt(&self, path: &str) -> bool {
+        self.cfg
+            .exempt_paths
+            .iter()
+            .any(|exempt| match exempt.strip_suffix("/*") {
+                Some(prefix) => path == prefix || path.starts_with(&format!("{prefix}/")),
+                None => path == exempt,
+            })
+    }
+
+    fn origin_allowed(&self, headers: &HeaderMap) -> bool {
+        let origin = headers
+            .get(header::ORIGIN)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+            .or_else(|| {
+                let referer = headers.get(header::REFERER)?.to_str().ok()?;
+                let (scheme, rest) = referer.split_once("://")?;
+                let host = rest.split(['/', '?', '#']).next()?;
+                Some(format!("{scheme}://{host}"))
+            });
+        // Browsers send `Origin` on cross-site unsafe requests; without either header the token
+        // check alone decides.
+        let Some(origin) = origin else {
+            return true;
+        };
+        if self.cfg.allowed_origins.is_empty() {
+            let host = headers.get(header::HOST).and_then(|v| v.to_str().ok());
+            return host.is_some_and(|host| {
+                origin
+                    .split_once("://")
+                    .is_some_and(|(_, origin_host)| origin_host.eq_ignore_ascii_case(host))
+            });
+        }
+        self.cfg
+            .allowed_origins
+            .iter()
+            .any(|allowed| allowed.trim_end_matches('/').eq_ignore_ascii_case(&origin))
+    }
+
+    fn cookie(&self, token: &str) -> HeaderValue {
+        let secure = if self.cfg.cookie_secure {
+            "; Secure"
+        } else {
+            ""
+        };
+        let cookie = format!(
+            "{}={token}; Path=/; SameSite=Lax{secure}",
+            self.cfg.cookie_name
+        );
+        HeaderValue::from_str(&cookie).expect("csrf cookie is a valid header value")
+    }
+}
+
+/// Double-submit-cookie CSRF protection with signed tokens.
+///
+/// Every response to a client without a valid token sets the CSRF cookie, which is readable by
+/// scripts. Unsafe methods (anything but `GET`, `HEAD`, `OPTIONS` and `TRACE`) must echo it in the
+/// `X-CSRF-Token` header, and their `Origin` (or `Referer`) must be allowed. Failures are `403`
+/// `CommonError`s. The token is also available to handlers as a [`CsrfToken`] extension.
+///
+/// Tokens are bound to the value of `auth_cookie`, so one planted from another session is
+/// rejected, and a new token is issued whenever that cookie changes, such as after login.
+///
+/// ```rust,ignore
+/// let mut cfg = CsrfCfg::new(settings.csrf_secret);
+/// cfg.auth_cookie = Some("access_token".into());
+///
+/// let app = Router::new()
+///     .route("/profile", post(update_profile))
+///     .layer(middleware::from_fn(auth::<Jwt>))
+///     .layer(Extension(AuthCookie("access_token".into())))
+///     .layer(Extension(jwt_verifier))
+///     .layer(middleware::from_fn_with_state(Csrf::new(cfg)?, csrf));
+/// ```
+pub async fn csrf(State(csrf): State<Csrf>, mut req: Request, next: Next) -> Response {
+    let session = csrf.session(req.headers()).unwrap_or_default().to_string();
+    let cookie_token = read_cookie(req.headers(), &csrf.cfg.cookie_name)
+        .filter(|token| csrf.is_valid(token, &session))
+        .map(str::to_string);
+
+    let safe = matches!(
+        *req.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE
+    );
+    let uses_cookie_auth = csrf.cfg.auth_cookie.is_none() || !session.is_empty();
+    if !safe && uses_cookie_auth && !csrf.is_exempt(req.uri().path()) {
+        if csrf.cfg.check_origin && !csrf.origin_allowed(req.headers()) {
+            return api_error(StatusCode::FORBIDDEN, "origin not allowed").into_response();
+        }
+        let header_token = req
+            .headers()
+            .get(&csrf.header)
+            .and_then(|v| v.to_str().ok());
+        if cookie_token.is_none() || header_token != cookie_token.as_deref() {
+            return api_error(StatusCode::FORBIDDEN, "invalid csrf token").into_response();
+        }
+    }
+
+    let token = cookie_token.clone().unwrap_or_else(|| csrf.issue(&session));
+    req.extensions_mut().insert(CsrfToken(token.clone()));
+    let mut response = next.run(req).await;
+    if cookie_token.is_none() {
+        response
+            .headers_mut()
+            .append(header::SET_COOKIE, csrf.cookie(&token));
+    }
+    response
+}
+
+/// Value of the cookie `name`, from any `Cookie` header.
+pub(crate) fn read_cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .find_map(|pair| {
+            let (key, value) = pair.trim().split_once('=')?;
+            (key == name).then(|| value.trim_matches('"'))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Router, body::Body, middleware, routing::post};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn app(cfg: CsrfCfg) -> Router {
+        Router::new()
+            .route(
+                "/profile",
+                post(|| async { "updated" }).get(|| async { "profile" }),
+            )
+            .route("/webhooks/stripe", post(|| async { "hook" }))
+            .layer(middleware::from_fn_with_state(
+                Csrf::new(cfg).unwrap(),
+                csrf,
+            ))
+    }
+
+    async fn issued_token(app: &Router) -> String {
+        let response = app
+            .clone()
+            .oneshot(Request::get("/profile").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+        assert!(cookie.contains("SameSite=Lax; Secure"), "{cookie}");
+        let pair = cookie.split(';').next().unwrap();
+        pair.strip_prefix("csrf_token=").unwrap().to_string()
+    }
+
+    fn post_request(
+        uri: &str,
+        cookie: Option<&str>,
+        header: Option<&str>,
+        origin: &str,
+    ) -> Request {
+        let mut request = Request::post(uri)
+            .header(header::HOST, "app.example.com")
+            .header(header::ORIGIN, origin);
+        if let Some(cookie) = cookie {
+            request = request.header(header::COOKIE, cookie);
+        }
+        if let Some(token) = header {
+            request = request.header("x-csrf-token", token);
+        }
+        request.body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_double_submit_and_origin() {
+        let app = app(CsrfCfg::new("secret"));
+        let token = issued_token(&app).await;
+        let cookie = format!("csrf_token={token}");
+        let same_origin = "https://app.example.com";
+
+        let status = |request: Request| {
+            let app = app.clone();
+            async move { app.oneshot(request).await.unwrap().status() }
+        };
+        assert_eq!(
+            status(post_request(
+                "/profile",
+                Some(&cookie),
+                Some(&token),
+                same_origin
+            ))
+            .await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(post_request("/profile", Some(&cookie), None, same_origin)).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(post_request(
+                "/profile",
+                Some(&cookie),
+                Some(&token),
+                "https://evil.test"
+            ))
+            .await,
+            StatusCode::FORBIDDEN
+        );
+        let forged = "nonce.c2lnbmF0dXJl";
+        let forged_cookie = format!("csrf_token={forged}");
+        assert_eq!(
+            status(post_request(
+                "/profile",
+                Some(&forged_cookie),
+                Some(forged),
+                same_origin
+            ))
+            .await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(post_request(
+                "/webhooks/stripe",
+                None,
+                None,
+                "https://stripe.test"
+            ))
+            .await,
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[tokio::test]
+    async fn test_exempt_paths_and_header_auth() {
+        let mut cfg = CsrfCfg::new("secret");
+        cfg.exempt_paths = vec![String::from("/webhooks/*")];
+        cfg.auth_cookie = Some(String::from("access_token"));
+        let app = app(cfg);
+
+        let status = |request: Request| {
+            let app = app.clone();
+            async move { app.oneshot(request).await.unwrap().status() }
+        };
+        assert_eq!(
+            status(post_request(
+                "/webhooks/stripe",
+                Some("access_token=t"),
+                None,
+                "https://stripe.test"
+            ))
+            .await,
+            StatusCode::OK
+        );
+        // Bearer clients do not send the session cookie and are not checked.
+        assert_eq!(
+            status(post_request("/profile", None, None, "https://cli.test")).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(post_request(
+                "/profile",
+                Some("access_token=t"),
+                None,
+                "https://app.example.com"
+            ))
+            .await,
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[tokio::test]
+    async fn test_token_is_bound_to_auth_cookie() {
+        let mut cfg = CsrfCfg::new("secret");
+        cfg.auth_cookie = Some(String::from("access_token"));
+        let app = app(cfg);
+
+        // A token issued to the attacker's own session.
+        let response = app
+            .clone()
+            .oneshot(
+                Request::get("/profile")
+                    .header(header::COOKIE, "access_token=attacker")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+        let token = cookie
+            .split(';')
+            .next()
+            .unwrap()
+            .strip_prefix("csrf_token=")
+            .unwrap();
+
+        let status = |session: &str| {
+            let request = post_request(
+                "/profile",
+                Some(&format!("access_token={session}; csrf_token={token}")),
+                Some(token),
+                "https://app.example.com",
+            );
+            let app = app.clone();
+            async move { app.oneshot(request).await.unwrap().status() }
+        };
+        assert_eq!(status("attacker").await, StatusCode::OK);
+        assert_eq!(status("victim").await, StatusCode::FORBIDDEN);
+    }
+}
@@ -0,0 +1,155 @@
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+
+use crate::{problem::ProblemDetails, response::take_common_error};
+
+/// Body format used for failed requests.
+///
+/// Successful responses always keep the `CommonResponse` envelope.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorFormat {
+    /// `{code, message}` envelope.
+    #[default]
+    Common,
+    /// RFC 7807 `application/problem+json`.
+    Problem,
+}
+
+/// Rewrite error responses into the configured [`ErrorFormat`].
+///
+/// With [`ErrorFormat::Problem`], `CommonError` bodies and empty error bodies (such as the
+/// bare status codes returned by the auth middleware) become Problem Details, with the request
+/// path as `instance`. Other response headers are kept.
+///
+/// ```rust,ignore
+/// let app = Router::new()
+///     .route("/orders", post(create_order))
+///     .layer(middleware::from_fn_with_state(ErrorFormat::Problem, error_format));
+/// ```
+pub async fn error_format(State(format): State<ErrorFormat>, req: Request, next: Next) -> Response {
+    let instance = req.uri().path().to_string();
+    let response = next.run(req).await;
+    if format == ErrorFormat::Common {
+        return response;
+    }
+
+    let status = response.status();
+    let (headers, problem) = match take_common_error(response).await {
+        Ok((parts, error)) => (
+            parts.headers,
+            ProblemDetails::from_common_error(status, &error),
+        ),
+        Err(response) if is_empty_error(&response) => {
+            (response.headers().clone(), ProblemDetails::new(status))
+        }
+        Err(response) => return response,
+    };
+
+    let mut response = problem.with_instance(instance).into_response();
+    copy_headers(headers, response.headers_mut());
+    response
+}
+
+fn is_empty_error(response: &Response) -> bool {
+    let status = response.status();
+    (status.is_client_error() || status.is_server_error())
+        && !response.headers().contains_key(header::CONTENT_TYPE)
+}
+
+fn copy_headers(from: HeaderMap, to: &mut HeaderMap) {
+    for (name, value) in from.iter() {
+        if name != header::CONTENT_TYPE && name != header::CONTENT_LENGTH {
+            to.append(name.clone(), value.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        Router,
+        body::{Body, to_bytes},
+        http::{Request as HttpRequest, StatusCode},
+        middleware,
+        routing::get,
+    };
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::response::{ApiError, CommonError};
+
+    async fn failing() -> Result<&'static str, ApiError> {
+        Err((
+            StatusCode::NOT_FOUND,
+            CommonError::from((404, "order not found")).to_json(),
+        ))
+    }
+
+    async fn unauthorized() -> StatusCode {
+        StatusCode::UNAUTHORIZED
+    }
+
+    fn app(format: ErrorFormat) -> Router {
+        Router::new()
+            .route("/orders/1", get(failing))
+            .route("/me", get(unauthorized))
+            .route("/ok", get(|| async { "ok" }))
+            .layer(middleware::from_fn_with_state(format, error_format))
+    }
+
+    async fn call(app: Router, uri: &str) -> (StatusCode, Option<String>, Vec<u8>) {
+        let response = app
+            .oneshot(HttpRequest::get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .map(|v| v.to_str().unwrap().to_string());
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, content_type, body.to_vec())
+    }
+
+    #[tokio::test]
+    async fn test_common_error_becomes_problem() {
+        let (status, content_type, body) = call(app(ErrorFormat::Problem), "/orders/1").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(content_type.as_deref(), Some(crate::problem::PROBLEM_JSON));
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["type"], "about:blank");
+        assert_eq!(body["title"], "Not Found");
+        assert_eq!(body["status"], 404);
+        assert_eq!(body["detail"], "order not found");
+        assert_eq!(body["instance"], "/orders/1");
+        assert_eq!(body["code"], 404);
+    }
+
+    #[tokio::test]
+    async fn test_empty_error_becomes_problem() {
+        let (status, _, body) = call(app(ErrorFormat::Problem), "/me").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["title"], "Unauthorized");
+        assert!(body.get("detail").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_common_format_and_success_untouched() {
+        let (_, content_type, body) = call(app(ErrorFormat::Common), "/orders/1").await;
+        assert_eq!(content_type.as_deref(), Some("application/json"));
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["message"], "order not found");
+
+        let (status, _, body) = call(app(ErrorFormat::Problem), "/ok").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, b"ok");
+    }
+}
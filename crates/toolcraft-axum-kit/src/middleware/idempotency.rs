@@ -0,0 +1,623 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use axum::{
+    body::{Body, HttpBody, to_bytes},
+    extract::{Request, State},
+    http::{HeaderName, HeaderValue, Method, StatusCode, header, request::Parts},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::time::Instant;
+
+use crate::{
+    error::{Error, Result},
+    response::api_error,
+};
+
+pub const IDEMPOTENCY_KEY: HeaderName = HeaderName::from_static("idempotency-key");
+
+/// Set to `true` on responses replayed from the store.
+pub const IDEMPOTENT_REPLAYED: HeaderName = HeaderName::from_static("idempotent-replayed");
+
+/// A response recorded for an idempotency key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    /// `false` when the response was too large to record. The key stays used, but retries get
+    /// `409` instead of a replay.
+    #[serde(default = "default_replayable")]
+    pub replayable: bool,
+}
+
+fn default_replayable() -> bool {
+    true
+}
+
+/// What the store knows about a key when a request claims it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdempotencyState {
+    /// The key was free and is now held by this request.
+    Started,
+    /// Another request holds the key.
+    InFlight { fingerprint: String },
+    /// A response was already recorded.
+    Completed {
+        fingerprint: String,
+        response: StoredResponse,
+    },
+}
+
+/// Storage for idempotency records, so they can be shared between instances.
+pub trait IdempotencyStore: Send + Sync + 'static {
+    /// Atomically claim `key` for a request with `fingerprint`, holding it for at most `lock_ttl`,
+    /// or report what is already stored.
+    fn claim(
+        &self,
+        key: &str,
+        fingerprint: &str,
+        lock_ttl: Duration,
+    ) -> impl Future<Output = Result<IdempotencyState>> + Send;
+
+    /// Record the response for a claimed key, keeping it for `ttl`.
+    fn complete(
+        &self,
+        key: &str,
+        response: StoredResponse,
+        ttl: Duration,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Drop a claim without recording a response, so the request can be retried.
+    fn release(&self, key: &str) -> impl Future<Output = Result<()>> + Send;
+}
+
+type Records = HashMap<String, (IdempotencyState, Instant)>;
+
+/// Process-local [`IdempotencyStore`]. Expired records are pruned on each claim.
+#[derive(Debug, Default)]
+pub struct MemoryIdempotencyStore {
+    records: Mutex<Records>,
+}
+
+impl MemoryIdempotencyStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn records(&self) -> Result<MutexGuard<'_, Records>> {
+        self.records
+            .lock()
+            .map_err(|_| Error::ErrorMessage("idempotency store poisoned".into()))
+    }
+}
+
+impl IdempotencyStore for MemoryIdempotencyStore {
+    async fn claim(
+        &self,
+        key: &str,
+        fingerprint: &str,
+        lock_ttl: Duration,
+    ) -> Result<IdempotencyState> {
+        let now = Instant::now();
+        let mut records = self.records()?;
+        records.retain(|_, (_, expires)| *expires > now);
+        if let Some((state, _)) = records.get(key) {
+            return Ok(state.clone());
+        }
+        records.insert(
+            key.to_string(),
+            (
+                IdempotencyState::InFlight {
+                    fingerprint: fingerprint.to_string(),
+                },
+                now + lock_ttl,
+            ),
+        );
+        Ok(IdempotencyState::Started)
+    }
+
+    async fn complete(&self, key: &str, response: StoredResponse, ttl: Duration) -> Result<()> {
+        let mut records = self.records()?;
+        if let Some((state, expires)) = records.get_mut(key) {
+            let fingerprint = match state {
+                IdempotencyState::InFlight { fingerprint } => std::mem::take(fingerprint),
+                _ => return Ok(()),
+            };
+            *state = IdempotencyState::Completed {
+                fingerprint,
+                response,
+            };
+            *expires = Instant::now() + ttl;
+        }
+        Ok(())
+    }
+
+    async fn release(&self, key: &str) -> Result<()> {
+        self.records()?.remove(key);
+        Ok(())
+    }
+}
+
+type ScopeFn = dyn Fn(&Parts) -> Option<String> + Send + Sync;
+
+/// Settings for the [`idempotency`] middleware.
+pub struct Idempotency<S = MemoryIdempotencyStore> {
+    store: Arc<S>,
+    scope: Arc<ScopeFn>,
+    ttl: Duration,
+    lock_ttl: Duration,
+    max_body: usize,
+}
+
+impl<S> Clone for Idempotency<S> {
+    fn clone(&self) -> Self {
+        Idempotency {
+            store: self.store.clone(),
+            scope: self.scope.clone(),
+            ttl: self.ttl,
+            lock_ttl: self.lock_ttl,
+            max_body: self.max_body,
+        }
+    }
+}
+
+impl Idempotency<MemoryIdempotencyStore> {
+    pub fn new() -> Self {
+        Self::with_store(Arc::new(MemoryIdempotencyStore::new()))
+    }
+}
+
+impl Default for Idempotency<MemoryIdempotencyStore> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Idempotency<S>
+where
+    S: IdempotencyStore,
+{
+    /// Records are kept for 24 hours; in-flight claims expire after 60 seconds. Keys are scoped
+    /// by `AuthUser`, which needs the `jwt` feature.
+    pub fn with_store(store: Arc<S>) -> Self {
+        Idempotency {
+            store,
+            scope: Arc::new(user_scope),
+            ttl: Duration::from_secs(24 * 60 * 60),
+            lock_ttl: Duration::from_secs(60),
+            max_body: 2 * 1024 * 1024,
+        }
+    }
+
+    /// Scope keys by something other than `AuthUser`, such as an API key. Requests without a
+    /// scope are passed through without idempotency handling, so clients cannot replay each
+    /// other's responses.
+    pub fn with_scope<F>(mut self, scope: F) -> Self
+    where
+        F: Fn(&Parts) -> Option<String> + Send + Sync + 'static,
+    {
+        self.scope = Arc::new(scope);
+        self
+    }
+
+    /// How long a recorded response is replayed.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// How long a claim survives a request that never completes, such as a crashed handler.
+    pub fn with_lock_ttl(mut self, lock_ttl: Duration) -> Self {
+        self.lock_ttl = lock_ttl;
+        self
+    }
+
+    /// Largest request body accepted with an `Idempotency-Key`, larger ones get `413`, and largest
+    /// response body recorded for replay. Larger or streamed responses are passed through and
+    /// their key is marked completed without a replayable response.
+    pub fn with_max_body(mut self, max_body: usize) -> Self {
+        self.max_body = max_body;
+        self
+    }
+}
+
+/// Honour `Idempotency-Key` on `POST` and `PATCH` requests.
+///
+/// Keys are scoped by `AuthUser`, so the layer should run inside the auth middleware; requests
+/// without a user (or another [`Idempotency::with_scope`]) are not deduplicated. The first
+/// response for a key is recorded, without `Set-Cookie`, and replayed with
+/// `Idempotent-Replayed: true` for retries with the same method, path and body. A retry while the
+/// first request is still running gets `409`, and a key reused for a different request gets `422`.
+/// Server errors are not recorded, so they can be retried. Responses over the body limit are sent
+/// as is, and retries of their key get `409` rather than running the handler again.
+///
+/// ```rust,ignore
+/// let app = Router::new()
+///     .route("/orders", post(create_order))
+///     .layer(middleware::from_fn_with_state(Idempotency::new(), idempotency::<MemoryIdempotencyStore>))
+///     .layer(middleware::from_fn(auth::<Jwt>))
+///     .layer(Extension(jwt_verifier));
+/// ```
+pub async fn idempotency<S>(State(cfg): State<Idempotency<S>>, req: Request, next: Next) -> Response
+where
+    S: IdempotencyStore,
+{
+    if !matches!(*req.method(), Method::POST | Method::PATCH) {
+        return next.run(req).await;
+    }
+    let Some(key) = req.headers().get(&IDEMPOTENCY_KEY) else {
+        return next.run(req).await;
+    };
+    let Some(key) = key
+        .to_str()
+        .ok()
+        .filter(|k| !k.is_empty() && k.len() <= 255)
+    else {
+        return api_error(StatusCode::BAD_REQUEST, "invalid Idempotency-Key header")
+            .into_response();
+    };
+    let key = key.to_string();
+
+    let (parts, body) = req.into_parts();
+    let Some(scope) = (cfg.scope)(&parts) else {
+        return next.run(Request::from_parts(parts, body)).await;
+    };
+    let key = format!("{scope}:{key}");
+    let body = match to_bytes(body, cfg.max_body).await {
+        Ok(body) => body,
+        Err(_) => {
+            return api_error(StatusCode::PAYLOAD_TOO_LARGE, "request body too large")
+                .into_response();
+        }
+    };
+    let target = parts
+        .uri
+        .path_and_query()
+        .map_or(parts.uri.path(), |target| target.as_str());
+    let fingerprint = fingerprint(&parts.method, target, &body);
+
+    match cfg.store.claim(&key, &fingerprint, cfg.lock_ttl).await {
+        Ok(IdempotencyState::Started) => {}
+        Ok(IdempotencyState::InFlight { fingerprint: other }) => {
+            return if other == fingerprint {
+                api_error(
+                    StatusCode::CONFLICT,
+                    "a request with this Idempotency-Key is in progress",
+                )
+            } else {
+                key_reused()
+            }
+            .into_response();
+        }
+        Ok(IdempotencyState::Completed {
+            fingerprint: other,
+            response,
+        }) => {
+            return if other != fingerprint {
+                key_reused().into_response()
+            } else if response.replayable {
+                replay(response)
+            } else {
+                api_error(
+                    StatusCode::CONFLICT,
+                    "a request with this Idempotency-Key already completed and cannot be replayed",
+                )
+                .into_response()
+            };
+        }
+        Err(e) => {
+            tracing::warn!(error = %e, "idempotency store failed, running request");
+            return next.run(Request::from_parts(parts, Body::from(body))).await;
+        }
+    }
+
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+    if response.status().is_server_error() {
+        release(&cfg, &key).await;
+        return response;
+    }
+    let (parts, body) = response.into_parts();
+    let mut stored = StoredResponse {
+        status: parts.status.as_u16(),
+        headers: parts
+            .headers
+            .iter()
+            .filter(|(name, _)| *name != header::CONTENT_LENGTH && *name != header::SET_COOKIE)
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect(),
+        body: Vec::new(),
+        replayable: false,
+    };
+    // Only buffer bodies known to fit; the handler already ran, so the response must get through.
+    let fits = body
+        .size_hint()
+        .upper()
+        .is_some_and(|upper| upper <= cfg.max_body as u64);
+    if !fits {
+        tracing::warn!("response too large to record for Idempotency-Key");
+        complete(&cfg, &key, stored).await;
+        return Response::from_parts(parts, body);
+    }
+    let response = match to_bytes(body, cfg.max_body).await {
+        Ok(body) => {
+            stored.body = body.to_vec();
+            stored.replayable = true;
+            Response::from_parts(parts, Body::from(body))
+        }
+        Err(e) => {
+            tracing::warn!(error = %e, "failed to read response for Idempotency-Key");
+            api_error(StatusCode::INTERNAL_SERVER_ERROR, "failed to read response").into_response()
+        }
+    };
+    complete(&cfg, &key, stored).await;
+    response
+}
+
+fn user_scope(
+    #[cfg_attr(not(feature = "jwt"), allow(unused_variables))] parts: &Parts,
+) -> Option<String> {
+    #[cfg(feature = "jwt")]
+    if let Some(user) = parts
+        .extensions
+        .get::<crate::middleware::auth_mw::AuthUser>()
+    {
+        return Some(format!("user:{}", user.user_id));
+    }
+    None
+}
+
+fn fingerprint(method: &Method, path: &str, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(method.as_str().as_bytes());
+    hasher.update(b" ");
+    hasher.update(path.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(body);
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+fn key_reused() -> crate::response::ApiError {
+    api_error(
+        StatusCode::UNPROCESSABLE_ENTITY,
+        "Idempotency-Key was already used for a different request",
+    )
+}
+
+fn replay(stored: StoredResponse) -> Response {
+    let mut response = Response::new(Body::from(stored.body));
+    *response.status_mut() = StatusCode::from_u16(stored.status).unwrap_or(StatusCode::OK);
+    let headers = response.headers_mut();
+    for (name, value) in stored.headers {
+        if let (Ok(name), Ok(value)) = (HeaderName::try_from(name), HeaderValue::try_from(value)) {
+            headers.append(name, value);
+        }
+    }
+    headers.insert(IDEMPOTENT_REPLAYED, HeaderValue::from_static("true"));
+    response
+}
+
+async fn complete<S>(cfg: &Idempotency<S>, key: &str, stored: StoredResponse)
+where
+    S: IdempotencyStore,
+{
+    if let Err(e) = cfg.store.complete(key, stored, cfg.ttl).await {
+        tracing::warn!(error = %e, "failed to record response for Idempotency-Key");
+    }
+}
+
+async fn release<S>(cfg: &Idempotency<S>, key: &str)
+where
+    S: IdempotencyStore,
+{
+    if let Err(e) = cfg.store.release(key).await {
+        tracing::warn!(error = %e, "failed to release Idempotency-Key");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use axum::{Router, middleware, routing::post};
+    use serde_json::Value;
+    use tokio::sync::Notify;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    /// Scopes keys by the `x-client` header.
+    fn client_scoped() -> Idempotency {
+        Idempotency::new().with_scope(|parts| {
+            let client = parts.headers.get("x-client")?.to_str().ok()?;
+            Some(format!("client:{client}"))
+        })
+    }
+
+    fn app(calls: Arc<AtomicUsize>, gate: Option<Arc<Notify>>) -> Router {
+        app_with(client_scoped(), calls, gate)
+    }
+
+    fn app_with(cfg: Idempotency, calls: Arc<AtomicUsize>, gate: Option<Arc<Notify>>) -> Router {
+        Router::new()
+            .route(
+                "/orders",
+                post(move |body: String| async move {
+                    let n = calls.fetch_add(1, Ordering::SeqCst) + 1;
+                    if let Some(gate) = gate {
+                        gate.notified().await;
+                    }
+                    (
+                        StatusCode::CREATED,
+                        [
+                            ("x-order", n.to_string()),
+                            ("set-cookie", format!("session={n}")),
+                        ],
+                        format!("order {n}: {body}"),
+                    )
+                }),
+            )
+            .layer(middleware::from_fn_with_state(
+                cfg,
+                idempotency::<MemoryIdempotencyStore>,
+            ))
+    }
+
+    fn request(key: &str, body: &str) -> Request {
+        request_to("/orders", key, body)
+    }
+
+    fn request_to(uri: &str, key: &str, body: &str) -> Request {
+        Request::post(uri)
+            .header("x-client", "alice")
+            .header(&IDEMPOTENCY_KEY, key)
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    async fn send(app: &Router, request: Request) -> (StatusCode, Option<String>, String) {
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let replayed = response
+            .headers()
+            .get(&IDEMPOTENT_REPLAYED)
+            .map(|v| v.to_str().unwrap().to_string());
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, replayed, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_retry_replays_first_response() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let app = app(calls.clone(), None);
+
+        let first = send(&app, request("k1", "pen")).await;
+        assert_eq!(
+            first,
+            (StatusCode::CREATED, None, String::from("order 1: pen"))
+        );
+
+        let retry = send(&app, request("k1", "pen")).await;
+        assert_eq!(
+            retry,
+            (
+                StatusCode::CREATED,
+                Some(String::from("true")),
+                String::from("order 1: pen")
+            )
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let (status, _, body) = send(&app, request("k1", "book")).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        let body: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["code"], 422);
+
+        let other = send(&app, request("k2", "pen")).await;
+        assert_eq!(other.2, "order 2: pen");
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_duplicate_conflicts() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let gate = Arc::new(Notify::new());
+        let app = app(calls.clone(), Some(gate.clone()));
+
+        let first = tokio::spawn({
+            let app = app.clone();
+            async move { send(&app, request("k1", "pen")).await }
+        });
+        while calls.load(Ordering::SeqCst) == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        let (status, _, _) = send(&app, request("k1", "pen")).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        gate.notify_one();
+        assert_eq!(first.await.unwrap().0, StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn test_requests_without_key_pass_through() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let app = app(calls.clone(), None);
+        for _ in 0 .. 2 {
+            let request = Request::post("/orders").body(Body::from("pen")).unwrap();
+            send(&app, request).await;
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_query_string_is_part_of_the_fingerprint() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let app = app(calls.clone(), None);
+        send(&app, request_to("/orders?dry_run=true", "k1", "pen")).await;
+        let (status, _, _) = send(&app, request_to("/orders?dry_run=false", "k1", "pen")).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_response_is_sent_but_not_rerun() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let app = app_with(client_scoped().with_max_body(8), calls.clone(), None);
+
+        let first = send(&app, request("k1", "pen")).await;
+        assert_eq!(
+            first,
+            (StatusCode::CREATED, None, String::from("order 1: pen"))
+        );
+
+        let (status, _, body) = send(&app, request("k1", "pen")).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(body.contains("cannot be replayed"), "{body}");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_keys_are_scoped_and_unscoped_requests_pass_through() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let app = app(calls.clone(), None);
+        send(&app, request("k1", "pen")).await;
+
+        let mut other = request("k1", "pen");
+        other
+            .headers_mut()
+            .insert("x-client", HeaderValue::from_static("mallory"));
+        assert_eq!(send(&app, other).await.2, "order 2: pen");
+
+        for _ in 0 .. 2 {
+            let mut anonymous = request("k1", "pen");
+            anonymous.headers_mut().remove("x-client");
+            let (_, replayed, _) = send(&app, anonymous).await;
+            assert_eq!(replayed, None);
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_replay_drops_set_cookie() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let app = app(calls, None);
+        let first = app.clone().oneshot(request("k1", "pen")).await.unwrap();
+        assert_eq!(first.headers()[header::SET_COOKIE], "session=1");
+
+        let retry = app.oneshot(request("k1", "pen")).await.unwrap();
+        assert_eq!(retry.headers()[&IDEMPOTENT_REPLAYED], "true");
+        assert_eq!(retry.headers()["x-order"], "1");
+        assert!(!retry.headers().contains_key(header::SET_COOKIE));
+    }
+}
@@ -1,4 +1,14 @@
+pub mod audit;
 pub mod cors;
+pub mod csrf;
+pub mod error_format;
+pub mod idempotency;
+pub mod rate_limit;
+pub mod request_id;
+pub mod service_layers;
+pub mod tenant;
 
+#[cfg(feature = "jwt")]
+pub mod api_key;
 #[cfg(feature = "jwt")]
 pub mod auth_mw;
@@ -0,0 +1,586 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex, Once},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode, request::Parts},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+
+use crate::{
+    error::{Error, Result},
+    response::api_error,
+};
+
+pub const RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+pub const RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+pub const RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+
+/// `limit` requests per `period_secs`, allowing bursts of up to `burst` requests.
+///
+/// Deserializes from e.g. `{ limit = 100, period_secs = 60, burst = 10 }`; `burst` defaults to
+/// `limit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct Quota {
+    pub limit: u32,
+    pub period_secs: u64,
+    #[serde(default)]
+    pub burst: Option<u32>,
+}
+
+impl Quota {
+    pub fn per_second(limit: u32) -> Self {
+        Self::new(limit, 1)
+    }
+
+    pub fn per_minute(limit: u32) -> Self {
+        Self::new(limit, 60)
+    }
+
+    /// # Panics
+    ///
+    /// When `limit` is zero.
+    pub fn new(limit: u32, period_secs: u64) -> Self {
+        assert!(limit > 0, "rate limit quota needs a limit above zero");
+        Quota {
+            limit,
+            period_secs,
+            burst: None,
+        }
+    }
+
+    /// # Panics
+    ///
+    /// When `burst` is zero.
+    pub fn with_burst(mut self, burst: u32) -> Self {
+        assert!(burst > 0, "rate limit quota needs a burst above zero");
+        self.burst = Some(burst);
+        self
+    }
+
+    fn burst(&self) -> u32 {
+        self.burst.unwrap_or(self.limit).max(1)
+    }
+
+    /// Requests advertised in `RateLimit-Limit`: the most a client can send at once.
+    fn advertised_limit(&self) -> u32 {
+        self.limit.max(self.burst())
+    }
+
+    /// Time between two requests at the sustained rate.
+    fn emission_interval(&self) -> Duration {
+        Duration::from_secs(self.period_secs) / self.limit.max(1)
+    }
+}
+
+/// Outcome of a rate limit check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitDecision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    /// Time until the full burst is available again.
+    pub reset_after: Duration,
+    /// Set when the request was rejected.
+    pub retry_after: Option<Duration>,
+}
+
+/// Generic cell rate algorithm.
+///
+/// Given the stored theoretical arrival time (`tat`, as time since the Unix epoch), returns the
+/// decision and the `tat` to store when the request is allowed. Shared stores can run this inside
+/// their own atomic update.
+pub fn gcra(
+    tat: Option<Duration>,
+    now: Duration,
+    quota: &Quota,
+) -> (RateLimitDecision, Option<Duration>) {
+    let interval = quota.emission_interval();
+    let burst = quota.burst();
+    let window = interval * burst;
+    let tat = tat.unwrap_or(now).max(now);
+    let new_tat = tat + interval;
+    let allow_at = new_tat.saturating_sub(window);
+
+    if now < allow_at {
+        let decision = RateLimitDecision {
+            allowed: false,
+            limit: quota.advertised_limit(),
+            remaining: 0,
+            reset_after: tat - now,
+            retry_after: Some(allow_at - now),
+        };
+        return (decision, None);
+    }
+
+    let used = new_tat - now;
+    let remaining = if interval.is_zero() {
+        burst
+    } else {
+        ((window.saturating_sub(used)).as_nanos() / interval.as_nanos()) as u32
+    };
+    let decision = RateLimitDecision {
+        allowed: true,
+        limit: quota.advertised_limit(),
+        remaining,
+        reset_after: used,
+        retry_after: None,
+    };
+    (decision, Some(new_tat))
+}
+
+/// Storage for rate limit state, so limits can be shared between instances.
+pub trait RateLimitStore: Send + Sync + 'static {
+    /// Check and record one request for `key`. Implementations must be atomic per key; most can
+    /// delegate the arithmetic to [`gcra`].
+    fn check(
+        &self,
+        key: &str,
+        quota: &Quota,
+    ) -> impl Future<Output = Result<RateLimitDecision>> + Send;
+}
+
+/// Process-local [`RateLimitStore`]. Expired keys are pruned as the map grows.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    state: Mutex<HashMap<String, Duration>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+const PRUNE_THRESHOLD: usize = 10_000;
+
+impl RateLimitStore for MemoryStore {
+    async fn check(&self, key: &str, quota: &Quota) -> Result<RateLimitDecision> {
+        let now = now();
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|_| Error::ErrorMessage("rate limit store poisoned".into()))?;
+        if state.len() >= PRUNE_THRESHOLD {
+            state.retain(|_, tat| *tat > now);
+        }
+        let (decision, tat) = gcra(state.get(key).copied(), now, quota);
+        if let Some(tat) = tat {
+            state.insert(key.to_string(), tat);
+        }
+        Ok(decision)
+    }
+}
+
+fn now() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+/// Proxies whose `X-Forwarded-For` entries are believed, as addresses or CIDR ranges.
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies {
+    networks: Vec<(IpAddr, u8)>,
+}
+
+impl TrustedProxies {
+    /// Parse entries such as `10.0.0.0/8`, `192.168.1.10` or `::1`.
+    pub fn new<I, T>(entries: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<str>,
+    {
+        let networks = entries
+            .into_iter()
+            .map(|entry| parse_network(entry.as_ref()))
+            .collect::<Result<_>>()?;
+        Ok(TrustedProxies { networks })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        self.networks
+            .iter()
+            .any(|(network, prefix)| in_network(ip, *network, *prefix))
+    }
+}
+
+fn parse_network(entry: &str) -> Result<(IpAddr, u8)> {
+    let invalid = || Error::ErrorMessage(format!("invalid trusted proxy: {entry}").into());
+    let (addr, prefix) = match entry.split_once('/') {
+        Some((addr, prefix)) => (addr, Some(prefix)),
+        None => (entry, None),
+    };
+    let addr: IpAddr = addr.trim().parse().map_err(|_| invalid())?;
+    let max = if addr.is_ipv4() { 32 } else { 128 };
+    let prefix = match prefix {
+        Some(prefix) => prefix.trim().parse::<u8>().map_err(|_| invalid())?,
+        None => max,
+    };
+    if prefix > max {
+        return Err(invalid());
+    }
+    Ok((addr, prefix))
+}
+
+fn in_network(ip: IpAddr, network: IpAddr, prefix: u8) -> bool {
+    match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+/// The client address: the connection peer, or, when the peer is a trusted proxy, the right-most
+/// `X-Forwarded-For` entry that is not itself a trusted proxy.
+///
+/// Requires the server to be started with `ConnectInfo<SocketAddr>`, as [`crate::start`] does.
+pub fn client_ip(parts: &Parts, trusted: &TrustedProxies) -> Option<IpAddr> {
+    let peer = parts
+        .extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip())?;
+    if !trusted.contains(peer) {
+        return Some(peer);
+    }
+    let forwarded = forwarded_for(&parts.headers);
+    forwarded
+        .iter()
+        .rev()
+        .find(|ip| !trusted.contains(**ip))
+        .or(forwarded.first())
+        .copied()
+        .or(Some(peer))
+}
+
+fn forwarded_for(headers: &HeaderMap) -> Vec<IpAddr> {
+    headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|ip| ip.trim().parse().ok())
+        .collect()
+}
+
+type KeyFn = dyn Fn(&Parts) -> Option<String> + Send + Sync;
+
+/// How requests are grouped into rate limit buckets.
+#[derive(Clone)]
+pub enum RateLimitKey {
+    /// `AuthUser.user_id`, falling back to the client IP for anonymous requests.
+    #[cfg(feature = "jwt")]
+    User(TrustedProxies),
+    /// The client IP.
+    Ip(TrustedProxies),
+    /// The value of a header such as `X-Api-Key`.
+    Header(HeaderName),
+    /// A custom key; requests without a key are not limited.
+    Custom(Arc<KeyFn>),
+}
+
+impl RateLimitKey {
+    pub fn custom<F>(f: F) -> Self
+    where
+        F: Fn(&Parts) -> Option<String> + Send + Sync + 'static,
+    {
+        RateLimitKey::Custom(Arc::new(f))
+    }
+
+    fn extract(&self, parts: &Parts) -> Option<String> {
+        match self {
+            #[cfg(feature = "jwt")]
+            RateLimitKey::User(trusted) => parts
+                .extensions
+                .get::<crate::middleware::auth_mw::AuthUser>()
+                .map(|user| format!("user:{}", user.user_id))
+                .or_else(|| client_ip(parts, trusted).map(|ip| format!("ip:{ip}"))),
+            RateLimitKey::Ip(trusted) => client_ip(parts, trusted).map(|ip| format!("ip:{ip}")),
+            RateLimitKey::Header(name) => parts
+                .headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| format!("header:{v}")),
+            RateLimitKey::Custom(f) => f(parts),
+        }
+    }
+
+    /// Whether the key falls back to the client IP, which needs `ConnectInfo<SocketAddr>`.
+    fn uses_client_ip(&self) -> bool {
+        match self {
+            #[cfg(feature = "jwt")]
+            RateLimitKey::User(_) => true,
+            RateLimitKey::Ip(_) => true,
+            RateLimitKey::Header(_) | RateLimitKey::Custom(_) => false,
+        }
+    }
+}
+
+static MISSING_CONNECT_INFO: Once = Once::new();
+
+/// A named limit, applied per route with [`rate_limit`].
+///
+/// The `name` scopes the stored keys, so two routes sharing a store keep separate buckets.
+pub struct RateLimiter<S = MemoryStore> {
+    name: Arc<str>,
+    quota: Quota,
+    key: RateLimitKey,
+    store: Arc<S>,
+}
+
+impl<S> Clone for RateLimiter<S> {
+    fn clone(&self) -> Self {
+        RateLimiter {
+            name: self.name.clone(),
+            quota: self.quota,
+            key: self.key.clone(),
+            store: self.store.clone(),
+        }
+    }
+}
+
+impl RateLimiter<MemoryStore> {
+    pub fn new(name: &str, quota: Quota, key: RateLimitKey) -> Self {
+        Self::with_store(name, quota, key, Arc::new(MemoryStore::new()))
+    }
+}
+
+impl<S> RateLimiter<S>
+where
+    S: RateLimitStore,
+{
+    /// # Panics
+    ///
+    /// When the quota, for example one loaded from config, has a zero `limit` or `burst`.
+    pub fn with_store(name: &str, quota: Quota, key: RateLimitKey, store: Arc<S>) -> Self {
+        assert!(
+            quota.limit > 0 && quota.burst != Some(0),
+            "rate limit quota for {name:?} needs a limit and burst above zero"
+        );
+        RateLimiter {
+            name: name.into(),
+            quota,
+            key,
+            store,
+        }
+    }
+}
+
+/// Throttle requests with a [`RateLimiter`].
+///
+/// Allowed responses carry `RateLimit-Limit`, `RateLimit-Remaining` and `RateLimit-Reset`;
+/// rejected ones are `429` `CommonError`s with `Retry-After`. Store failures let the request
+/// through, as do requests without a key. IP keys need the server to be started with
+/// `ConnectInfo<SocketAddr>`; without it nothing is limited and a warning is logged once.
+///
+/// ```rust,ignore
+/// let limiter = RateLimiter::new("export", Quota::per_minute(10), RateLimitKey::User(trusted));
+/// let app = Router::new().route(
+///     "/reports/export",
+///     post(export).layer(middleware::from_fn_with_state(limiter, rate_limit::<MemoryStore>)),
+/// );
+/// ```
+pub async fn rate_limit<S>(
+    State(limiter): State<RateLimiter<S>>,
+    req: Request,
+    next: Next,
+) -> Response
+where
+    S: RateLimitStore,
+{
+    let (parts, body) = req.into_parts();
+    let Some(key) = limiter.key.extract(&parts) else {
+        if limiter.key.uses_client_ip()
+            && parts.extensions.get::<ConnectInfo<SocketAddr>>().is_none()
+        {
+            MISSING_CONNECT_INFO.call_once(|| {
+                tracing::warn!(
+                    limiter = %limiter.name,
+                    "rate limit keyed by client IP, but the server was not started with \
+                     into_make_service_with_connect_info; requests are not limited"
+                );
+            });
+        }
+        return next.run(Request::from_parts(parts, body)).await;
+    };
+    let key = format!("{}:{key}", limiter.name);
+    let decision = match limiter.store.check(&key, &limiter.quota).await {
+        Ok(decision) => decision,
+        Err(e) => {
+            tracing::warn!(error = %e, "rate limit store failed, allowing request");
+            return next.run(Request::from_parts(parts, body)).await;
+        }
+    };
+
+    let mut response = if decision.allowed {
+        next.run(Request::from_parts(parts, body)).await
+    } else {
+        api_error(StatusCode::TOO_MANY_REQUESTS, "rate limit exceeded").into_response()
+    };
+    let headers = response.headers_mut();
+    headers.insert(RATELIMIT_LIMIT, HeaderValue::from(decision.limit));
+    headers.insert(RATELIMIT_REMAINING, HeaderValue::from(decision.remaining));
+    headers.insert(
+        RATELIMIT_RESET,
+        HeaderValue::from(ceil_secs(decision.reset_after)),
+    );
+    if let Some(retry_after) = decision.retry_after {
+        headers.insert(
+            axum::http::header::RETRY_AFTER,
+            HeaderValue::from(ceil_secs(retry_after)),
+        );
+    }
+    response
+}
+
+fn ceil_secs(duration: Duration) -> u64 {
+    duration.as_secs() + u64::from(duration.subsec_nanos() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        Router,
+        body::{Body, to_bytes},
+        http::header,
+        middleware,
+        routing::get,
+    };
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[test]
+    fn test_gcra_allows_burst_then_rejects() {
+        let quota = Quota::per_second(2);
+        let now = Duration::from_secs(1_000);
+        let (first, tat) = gcra(None, now, &quota);
+        assert!(first.allowed);
+        assert_eq!(first.remaining, 1);
+        let (second, tat) = gcra(tat, now, &quota);
+        assert!(second.allowed);
+        assert_eq!(second.remaining, 0);
+        let (third, none) = gcra(tat, now, &quota);
+        assert!(!third.allowed);
+        assert!(none.is_none());
+        assert_eq!(third.retry_after, Some(Duration::from_millis(500)));
+
+        let later = now + Duration::from_millis(500);
+        assert!(gcra(tat, later, &quota).0.allowed);
+    }
+
+    #[test]
+    fn test_burst_above_limit_is_advertised() {
+        let quota = Quota::new(2, 60).with_burst(5);
+        let (decision, _) = gcra(None, Duration::from_secs(1_000), &quota);
+        assert_eq!(decision.limit, 5);
+        assert_eq!(decision.remaining, 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "limit above zero")]
+    fn test_zero_limit_is_rejected() {
+        Quota::per_minute(0);
+    }
+
+    #[test]
+    #[should_panic(expected = "needs a limit and burst above zero")]
+    fn test_zero_limit_from_config_is_rejected() {
+        let quota: Quota = serde_json::from_str(r#"{"limit": 0, "period_secs": 60}"#).unwrap();
+        RateLimiter::new(
+            "config",
+            quota,
+            RateLimitKey::Header(HeaderName::from_static("x-key")),
+        );
+    }
+
+    #[test]
+    fn test_trusted_proxies() {
+        let trusted = TrustedProxies::new(["10.0.0.0/8", "::1"]).unwrap();
+        assert!(trusted.contains("10.1.2.3".parse().unwrap()));
+        assert!(trusted.contains("::1".parse().unwrap()));
+        assert!(!trusted.contains("11.0.0.1".parse().unwrap()));
+        assert!(TrustedProxies::new(["10.0.0.0/33"]).is_err());
+    }
+
+    fn request(peer: &str, forwarded: Option<&str>) -> Request<Body> {
+        let mut request = Request::get("/").body(Body::empty()).unwrap();
+        if let Some(forwarded) = forwarded {
+            request
+                .headers_mut()
+                .insert("x-forwarded-for", forwarded.parse().unwrap());
+        }
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(peer.parse::<SocketAddr>().unwrap()));
+        request
+    }
+
+    #[test]
+    fn test_client_ip_uses_forwarded_for_only_from_trusted_peers() {
+        let trusted = TrustedProxies::new(["10.0.0.0/8"]).unwrap();
+        let ip = |peer, forwarded| {
+            let (parts, _) = request(peer, forwarded).into_parts();
+            client_ip(&parts, &trusted).unwrap().to_string()
+        };
+        assert_eq!(ip("1.2.3.4:80", Some("5.6.7.8")), "1.2.3.4");
+        assert_eq!(
+            ip("10.0.0.1:80", Some("9.9.9.9, 5.6.7.8, 10.0.0.2")),
+            "5.6.7.8"
+        );
+        assert_eq!(ip("10.0.0.1:80", None), "10.0.0.1");
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_rejects_with_headers() {
+        let limiter = RateLimiter::new(
+            "test",
+            Quota::per_minute(1),
+            RateLimitKey::Ip(TrustedProxies::default()),
+        );
+        let app = Router::new().route(
+            "/",
+            get(|| async { "ok" }).layer(middleware::from_fn_with_state(
+                limiter,
+                rate_limit::<MemoryStore>,
+            )),
+        );
+
+        let response = app
+            .clone()
+            .oneshot(request("1.2.3.4:80", None))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[RATELIMIT_LIMIT], "1");
+        assert_eq!(response.headers()[RATELIMIT_REMAINING], "0");
+
+        let response = app
+            .clone()
+            .oneshot(request("1.2.3.4:80", None))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "60");
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], 429);
+
+        let response = app.oneshot(request("4.3.2.1:80", None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,192 @@
+use std::time::Instant;
+
+use axum::{
+    Json, Router,
+    extract::{MatchedPath, Request},
+    http::{HeaderName, HeaderValue, header},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+};
+use tracing::{Instrument, field};
+use uuid::Uuid;
+
+use crate::response::take_common_error;
+
+pub const X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Correlation id of the current request, available to handlers as `Extension<RequestId>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+/// Add [`request_id`] and [`access_log`] to every route, in the right order.
+pub fn with_request_tracing<S>(router: Router<S>) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    router
+        .layer(middleware::from_fn(access_log))
+        .layer(middleware::from_fn(request_id))
+}
+
+/// Assign or propagate `X-Request-Id`.
+///
+/// A well-formed incoming id is kept, otherwise a UUIDv4 is generated. The id is stored as a
+/// [`RequestId`] extension, echoed in the response headers and added to `CommonError` bodies.
+pub async fn request_id(mut req: Request, next: Next) -> Response {
+    let id = req
+        .headers()
+        .get(&X_REQUEST_ID)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| is_valid_request_id(v))
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    let header_value = HeaderValue::from_str(&id).expect("request id is a valid header value");
+    req.headers_mut().insert(X_REQUEST_ID, header_value.clone());
+    req.extensions_mut().insert(RequestId(id.clone()));
+
+    let response = next.run(req).await;
+    let mut response = match take_common_error(response).await {
+        Ok((mut parts, mut error)) => {
+            error.request_id.get_or_insert(id);
+            parts.headers.remove(header::CONTENT_LENGTH);
+            (parts, Json(error)).into_response()
+        }
+        Err(response) => response,
+    };
+    response.headers_mut().insert(X_REQUEST_ID, header_value);
+    response
+}
+
+/// Wrap the request in an `http_request` span and log its outcome.
+///
+/// The span records `method`, the matched `route` template, `request_id`, `status`,
+/// `latency_ms` and, behind the auth middleware, `user_id`. Server errors are logged at `ERROR`,
+/// everything else at `INFO`.
+pub async fn access_log(req: Request, next: Next) -> Response {
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| req.uri().path().to_string());
+    let request_id = req
+        .extensions()
+        .get::<RequestId>()
+        .map(|id| id.0.clone())
+        .unwrap_or_default();
+    let span = tracing::info_span!(
+        "http_request",
+        method = %req.method(),
+        route,
+        request_id,
+        status = field::Empty,
+        latency_ms = field::Empty,
+        user_id = field::Empty,
+    );
+
+    let start = Instant::now();
+    let response = next.run(req).instrument(span.clone()).await;
+    let status = response.status();
+    span.record("status", status.as_u16());
+    span.record("latency_ms", start.elapsed().as_millis() as u64);
+    #[cfg(feature = "jwt")]
+    if let Some(user) = response
+        .extensions()
+        .get::<crate::middleware::auth_mw::AuthUser>()
+    {
+        span.record("user_id", user.user_id.as_str());
+    }
+
+    span.in_scope(|| {
+        if status.is_server_error() {
+            tracing::error!("request failed");
+        } else {
+            tracing::info!("request completed");
+        }
+    });
+    response
+}
+
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= 128 && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        Extension,
+        body::{Body, to_bytes},
+        http::StatusCode,
+        routing::get,
+    };
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::response::{ApiError, CommonError};
+
+    async fn echo(Extension(id): Extension<RequestId>) -> String {
+        id.0
+    }
+
+    async fn failing() -> Result<(), ApiError> {
+        Err((
+            StatusCode::CONFLICT,
+            CommonError::from((409, "duplicate")).to_json(),
+        ))
+    }
+
+    fn app() -> Router {
+        with_request_tracing(
+            Router::new()
+                .route("/echo", get(echo))
+                .route("/fail", get(failing)),
+        )
+    }
+
+    async fn call(request: axum::http::Request<Body>) -> (String, Vec<u8>) {
+        let response = app().oneshot(request).await.unwrap();
+        let id = response.headers()[&X_REQUEST_ID]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (id, body.to_vec())
+    }
+
+    #[tokio::test]
+    async fn test_request_id_is_generated() {
+        let (id, body) = call(Request::get("/echo").body(Body::empty()).unwrap()).await;
+        assert!(Uuid::parse_str(&id).is_ok());
+        assert_eq!(body, id.as_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_request_id_is_propagated() {
+        let request = Request::get("/echo")
+            .header(&X_REQUEST_ID, "abc-123")
+            .body(Body::empty())
+            .unwrap();
+        let (id, body) = call(request).await;
+        assert_eq!(id, "abc-123");
+        assert_eq!(body, b"abc-123");
+
+        let request = Request::get("/echo")
+            .header(&X_REQUEST_ID, "has space")
+            .body(Body::empty())
+            .unwrap();
+        let (id, _) = call(request).await;
+        assert_ne!(id, "has space");
+    }
+
+    #[tokio::test]
+    async fn test_request_id_in_common_error() {
+        let request = Request::get("/fail")
+            .header(&X_REQUEST_ID, "req-9")
+            .body(Body::empty())
+            .unwrap();
+        let (_, body) = call(request).await;
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["message"], "duplicate");
+        assert_eq!(body["request_id"], "req-9");
+    }
+}
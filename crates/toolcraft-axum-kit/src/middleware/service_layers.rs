@@ -0,0 +1,466 @@
+use std::{any::Any, time::Duration};
+
+use axum::{
+    Router,
+    extract::{DefaultBodyLimit, Request, State},
+    http::{HeaderValue, StatusCode, header},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use tower_http::{
+    catch_panic::CatchPanicLayer, compression::CompressionLayer,
+    decompression::RequestDecompressionLayer, limit::RequestBodyLimitLayer,
+    set_header::SetResponseHeaderLayer,
+};
+
+use crate::{
+    error::{Error, Result},
+    response::api_error,
+};
+
+/// Settings for [`ServiceLayers`], typically loaded from a config file.
+///
+/// ```toml
+/// [http]
+/// body_limit = 10485760
+/// timeout_secs = 15
+/// timeout_status = 503
+/// frame_options = "SAMEORIGIN"
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ServiceLayersCfg {
+    /// Largest accepted request body in bytes, after decompression; `None` disables the limit.
+    #[serde(default = "default_body_limit")]
+    pub body_limit: Option<usize>,
+    /// Time allowed to produce response headers; `None` disables the timeout.
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: Option<u64>,
+    /// Status of timed out requests, `408` or `503`.
+    #[serde(default = "default_timeout_status")]
+    pub timeout_status: u16,
+    #[serde(default = "default_true")]
+    pub compression: bool,
+    #[serde(default = "default_true")]
+    pub decompression: bool,
+    #[serde(default = "default_true")]
+    pub catch_panic: bool,
+    /// `Strict-Transport-Security` max-age; `None` omits the header.
+    #[serde(default = "default_hsts_max_age_secs")]
+    pub hsts_max_age_secs: Option<u64>,
+    #[serde(default = "default_true")]
+    pub hsts_include_subdomains: bool,
+    /// Send `X-Content-Type-Options: nosniff`.
+    #[serde(default = "default_true")]
+    pub content_type_nosniff: bool,
+    /// `X-Frame-Options` value; `None` omits the header.
+    #[serde(default = "default_frame_options")]
+    pub frame_options: Option<String>,
+}
+
+fn default_body_limit() -> Option<usize> {
+    Some(2 * 1024 * 1024)
+}
+
+fn default_timeout_secs() -> Option<u64> {
+    Some(30)
+}
+
+fn default_timeout_status() -> u16 {
+    StatusCode::REQUEST_TIMEOUT.as_u16()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_hsts_max_age_secs() -> Option<u64> {
+    Some(365 * 24 * 60 * 60)
+}
+
+fn default_frame_options() -> Option<String> {
+    Some(String::from("DENY"))
+}
+
+impl Default for ServiceLayersCfg {
+    fn default() -> Self {
+        ServiceLayersCfg {
+            body_limit: default_body_limit(),
+            timeout_secs: default_timeout_secs(),
+            timeout_status: default_timeout_status(),
+            compression: true,
+            decompression: true,
+            catch_panic: true,
+            hsts_max_age_secs: default_hsts_max_age_secs(),
+            hsts_include_subdomains: true,
+            content_type_nosniff: true,
+            frame_options: default_frame_options(),
+        }
+    }
+}
+
+/// The tower-http layers every service needs, with failures reported as `CommonError`s.
+///
+/// From the outside in: security headers are added, responses are compressed, panics become
+/// `500`, slow requests time out with `408` (or `503`), request bodies are decompressed and
+/// limited (`413`). Each layer can be turned off.
+///
+/// ```rust,ignore
+/// let layers = ServiceLayers::from_cfg(&settings.http)?;
+/// let app = layers.apply(Router::new().route("/orders", post(create_order)));
+///
+/// // or in code
+/// let app = ServiceLayers::new()
+///     .with_body_limit(Some(10 * 1024 * 1024))
+///     .with_timeout(Some(Duration::from_secs(5)))
+///     .apply(router);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ServiceLayers {
+    body_limit: Option<usize>,
+    timeout: Option<Duration>,
+    timeout_status: StatusCode,
+    compression: bool,
+    decompression: bool,
+    catch_panic: bool,
+    hsts: Option<HeaderValue>,
+    content_type_nosniff: bool,
+    frame_options: Option<HeaderValue>,
+}
+
+impl Default for ServiceLayers {
+    fn default() -> Self {
+        Self::from_cfg(&ServiceLayersCfg::default()).expect("default settings are valid")
+    }
+}
+
+impl ServiceLayers {
+    /// All layers with the [`ServiceLayersCfg`] defaults.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_cfg(cfg: &ServiceLayersCfg) -> Result<Self> {
+        let timeout_status = match StatusCode::from_u16(cfg.timeout_status) {
+            Ok(status @ (StatusCode::REQUEST_TIMEOUT | StatusCode::SERVICE_UNAVAILABLE)) => status,
+            _ => {
+                return Err(Error::ErrorMessage(
+                    "timeout_status must be 408 or 503".into(),
+                ));
+            }
+        };
+        let frame_options = cfg
+            .frame_options
+            .as_deref()
+            .map(HeaderValue::from_str)
+            .transpose()
+            .map_err(|_| Error::ErrorMessage("invalid frame_options".into()))?;
+        Ok(ServiceLayers {
+            body_limit: cfg.body_limit,
+            timeout: cfg.timeout_secs.map(Duration::from_secs),
+            timeout_status,
+            compression: cfg.compression,
+            decompression: cfg.decompression,
+            catch_panic: cfg.catch_panic,
+            hsts: cfg
+                .hsts_max_age_secs
+                .map(|max_age| hsts(max_age, cfg.hsts_include_subdomains)),
+            content_type_nosniff: cfg.content_type_nosniff,
+            frame_options,
+        })
+    }
+
+    pub fn with_body_limit(mut self, body_limit: Option<usize>) -> Self {
+        self.body_limit = body_limit;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Answer timed out requests with `503 Service Unavailable` instead of `408`.
+    pub fn with_timeout_unavailable(mut self) -> Self {
+        self.timeout_status = StatusCode::SERVICE_UNAVAILABLE;
+        self
+    }
+
+    pub fn with_compression(mut self, compression: bool) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    pub fn with_decompression(mut self, decompression: bool) -> Self {
+        self.decompression = decompression;
+        self
+    }
+
+    pub fn with_catch_panic(mut self, catch_panic: bool) -> Self {
+        self.catch_panic = catch_panic;
+        self
+    }
+
+    /// `Strict-Transport-Security` with `max_age` and `includeSubDomains`; `None` omits it.
+    pub fn with_hsts(mut self, max_age: Option<Duration>) -> Self {
+        self.hsts = max_age.map(|max_age| hsts(max_age.as_secs(), true));
+        self
+    }
+
+    pub fn with_content_type_nosniff(mut self, nosniff: bool) -> Self {
+        self.content_type_nosniff = nosniff;
+        self
+    }
+
+    pub fn with_frame_options(mut self, frame_options: Option<HeaderValue>) -> Self {
+        self.frame_options = frame_options;
+        self
+    }
+
+    /// Wrap every route of `router` in the configured layers.
+    ///
+    /// Security headers set by handlers are left untouched.
+    pub fn apply<S>(&self, router: Router<S>) -> Router<S>
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        let mut router = match self.body_limit {
+            Some(limit) => router
+                .layer(DefaultBodyLimit::max(limit))
+                .layer(RequestBodyLimitLayer::new(limit)),
+            None => router.layer(DefaultBodyLimit::disable()),
+        };
+        if self.decompression {
+            router = router.layer(RequestDecompressionLayer::new());
+        }
+        router = router.layer(middleware::from_fn(body_errors));
+        if let Some(timeout) = self.timeout {
+            router = router.layer(middleware::from_fn_with_state(
+                (timeout, self.timeout_status),
+                request_timeout,
+            ));
+        }
+        if self.catch_panic {
+            router = router.layer(CatchPanicLayer::custom(panic_response));
+        }
+        if self.compression {
+            router = router.layer(CompressionLayer::new());
+        }
+        if let Some(hsts) = &self.hsts {
+            router = router.layer(SetResponseHeaderLayer::if_not_present(
+                header::STRICT_TRANSPORT_SECURITY,
+                hsts.clone(),
+            ));
+        }
+        if self.content_type_nosniff {
+            router = router.layer(SetResponseHeaderLayer::if_not_present(
+                header::X_CONTENT_TYPE_OPTIONS,
+                HeaderValue::from_static("nosniff"),
+            ));
+        }
+        if let Some(frame_options) = &self.frame_options {
+            router = router.layer(SetResponseHeaderLayer::if_not_present(
+                header::X_FRAME_OPTIONS,
+                frame_options.clone(),
+            ));
+        }
+        router
+    }
+}
+
+fn hsts(max_age: u64, include_subdomains: bool) -> HeaderValue {
+    let value = if include_subdomains {
+        format!("max-age={max_age}; includeSubDomains")
+    } else {
+        format!("max-age={max_age}")
+    };
+    HeaderValue::from_str(&value).expect("hsts is a valid header value")
+}
+
+async fn request_timeout(
+    State((timeout, status)): State<(Duration, StatusCode)>,
+    req: Request,
+    next: Next,
+) -> Response {
+    match tokio::time::timeout(timeout, next.run(req)).await {
+        Ok(response) => response,
+        Err(_) => api_error(status, "request timed out").into_response(),
+    }
+}
+
+/// Turn the plain-text `413` and `415` answers of the body limit and decompression layers, and of
+/// axum's body extractors, into `CommonError`s.
+async fn body_errors(req: Request, next: Next) -> Response {
+    let response = next.run(req).await;
+    let message = match response.status() {
+        StatusCode::PAYLOAD_TOO_LARGE => "request body too large",
+        // The decompression layer lists the encodings it does accept.
+        StatusCode::UNSUPPORTED_MEDIA_TYPE
+            if response.headers().contains_key(header::ACCEPT_ENCODING) =>
+        {
+            "unsupported content encoding"
+        }
+        StatusCode::UNSUPPORTED_MEDIA_TYPE => "unsupported media type",
+        _ => return response,
+    };
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    if is_json {
+        return response;
+    }
+    api_error(response.status(), message).into_response()
+}
+
+fn panic_response(panic: Box<dyn Any + Send + 'static>) -> Response {
+    let detail = panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic");
+    tracing::error!(panic = detail, "handler panicked");
+    api_error(StatusCode::INTERNAL_SERVER_ERROR, "internal server error").into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        Json,
+        body::{Body, to_bytes},
+        routing::{get, post},
+    };
+    use serde_json::{Value, json};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn app(layers: ServiceLayers) -> Router {
+        layers.apply(
+            Router::new()
+                .route("/echo", post(|body: String| async move { body }))
+                .route(
+                    "/json",
+                    post(|Json(body): Json<Value>| async move { Json(body) }),
+                )
+                .route("/big", get(|| async { "x".repeat(4096) }))
+                .route(
+                    "/slow",
+                    get(|| async {
+                        tokio::time::sleep(Duration::from_secs(5)).await;
+                        "late"
+                    }),
+                )
+                .route(
+                    "/panic",
+                    get(|| async {
+                        if true {
+                            panic!("boom");
+                        }
+                        "unreachable"
+                    }),
+                ),
+        )
+    }
+
+    async fn send(app: Router, request: Request) -> (Response, Value) {
+        let response = app.oneshot(request).await.unwrap();
+        let (parts, body) = response.into_parts();
+        let bytes = to_bytes(body, usize::MAX).await.unwrap();
+        let json = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+        (Response::from_parts(parts, Body::empty()), json)
+    }
+
+    #[tokio::test]
+    async fn test_failures_become_common_errors() {
+        let layers = ServiceLayers::new()
+            .with_body_limit(Some(8))
+            .with_timeout(Some(Duration::from_millis(20)));
+
+        let request = Request::post("/echo")
+            .body(Body::from("more than eight bytes"))
+            .unwrap();
+        let (response, body) = send(app(layers.clone()), request).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(
+            body,
+            json!({"code": 413, "message": "request body too large"})
+        );
+
+        let request = Request::post("/echo")
+            .header(header::CONTENT_ENCODING, "x-unknown")
+            .body(Body::from("{}"))
+            .unwrap();
+        let (response, body) = send(app(layers.clone()), request).await;
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(body["message"], "unsupported content encoding");
+
+        let request = Request::post("/json")
+            .header(header::CONTENT_TYPE, "text/plain")
+            .body(Body::from("{}"))
+            .unwrap();
+        let (response, body) = send(app(layers.clone()), request).await;
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(body["message"], "unsupported media type");
+
+        let request = Request::get("/slow").body(Body::empty()).unwrap();
+        let (response, body) = send(app(layers.clone()), request).await;
+        assert_eq!(response.status(), StatusCode::REQUEST_TIMEOUT);
+        assert_eq!(body["code"], 408);
+
+        let (response, body) = send(
+            app(layers.with_timeout_unavailable()),
+            Request::get("/slow").body(Body::empty()).unwrap(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["code"], 503);
+
+        let request = Request::get("/panic").body(Body::empty()).unwrap();
+        let (response, body) = send(app(ServiceLayers::new()), request).await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(
+            body,
+            json!({"code": 500, "message": "internal server error"})
+        );
+        assert_eq!(response.headers()[header::X_FRAME_OPTIONS], "DENY");
+    }
+
+    #[tokio::test]
+    async fn test_security_headers_and_compression() {
+        let request = Request::get("/big")
+            .header(header::ACCEPT_ENCODING, "gzip")
+            .body(Body::empty())
+            .unwrap();
+        let (response, _) = send(app(ServiceLayers::new()), request).await;
+        let headers = response.headers();
+        assert_eq!(headers[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(
+            headers[header::STRICT_TRANSPORT_SECURITY],
+            "max-age=31536000; includeSubDomains"
+        );
+        assert_eq!(headers[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+        assert_eq!(headers[header::X_FRAME_OPTIONS], "DENY");
+    }
+
+    #[test]
+    fn test_cfg_validation() {
+        let cfg: ServiceLayersCfg = serde_json::from_value(json!({
+            "timeout_status": 503,
+            "hsts_max_age_secs": null,
+            "frame_options": "SAMEORIGIN"
+        }))
+        .unwrap();
+        assert_eq!(cfg.body_limit, Some(2 * 1024 * 1024));
+        let layers = ServiceLayers::from_cfg(&cfg).unwrap();
+        assert_eq!(layers.timeout_status, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(layers.hsts.is_none());
+
+        let cfg = ServiceLayersCfg {
+            timeout_status: 500,
+            ..ServiceLayersCfg::default()
+        };
+        assert!(ServiceLayers::from_cfg(&cfg).is_err());
+    }
+}
@@ -0,0 +1,374 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use axum::{
+    extract::{FromRequestParts, Request, State},
+    http::{StatusCode, header, request::Parts},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    error::{Error, Result},
+    response::{ApiError, api_error},
+};
+
+/// The tenant of the current request, resolved by the [`tenant`] middleware.
+///
+/// ```rust,ignore
+/// async fn list_orders(tenant: Tenant, Query(page): Query<PageQuery>) -> ResponseResult<Paginated<Order>> {
+///     let orders = repo.orders(&tenant.id, page).await?;
+///     Ok(Json(orders.into_common_response()))
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tenant {
+    pub id: String,
+    /// Whatever the registry keeps about the tenant, such as its plan or region.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl Tenant {
+    pub fn new(id: impl Into<String>) -> Self {
+        Tenant {
+            id: id.into(),
+            data: None,
+        }
+    }
+
+    pub fn with_data(mut self, data: Value) -> Self {
+        self.data = Some(data);
+        self
+    }
+}
+
+impl<S> FromRequestParts<S> for Tenant
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<Tenant>().cloned().ok_or_else(|| {
+            api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "tenant middleware is not installed",
+            )
+        })
+    }
+}
+
+/// Where the tenant id is read from.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum TenantSource {
+    /// The first label of the host under `base_domain`: `acme.example.com` is `acme` for
+    /// `example.com`. Nested subdomains and the bare domain are not tenants.
+    Subdomain { base_domain: String },
+    /// A request header, such as `X-Tenant-Id`.
+    Header { name: String },
+    /// A string claim in `AuthUser.ext`; the layer must run inside the auth middleware.
+    #[cfg(feature = "jwt")]
+    Claim { name: String },
+}
+
+/// Known tenants, for example a table in the main database.
+pub trait TenantRegistry: Send + Sync + 'static {
+    fn find(&self, id: &str) -> impl Future<Output = Result<Option<Tenant>>> + Send;
+}
+
+/// Process-local [`TenantRegistry`].
+#[derive(Debug, Default)]
+pub struct MemoryTenantRegistry {
+    tenants: Mutex<HashMap<String, Tenant>>,
+}
+
+impl MemoryTenantRegistry {
+    pub fn new<I>(tenants: I) -> Self
+    where
+        I: IntoIterator<Item = Tenant>,
+    {
+        MemoryTenantRegistry {
+            tenants: Mutex::new(tenants.into_iter().map(|t| (t.id.clone(), t)).collect()),
+        }
+    }
+
+    pub fn insert(&self, tenant: Tenant) -> Result<()> {
+        self.tenants()?.insert(tenant.id.clone(), tenant);
+        Ok(())
+    }
+
+    pub fn remove(&self, id: &str) -> Result<bool> {
+        Ok(self.tenants()?.remove(id).is_some())
+    }
+
+    fn tenants(&self) -> Result<MutexGuard<'_, HashMap<String, Tenant>>> {
+        self.tenants
+            .lock()
+            .map_err(|_| Error::ErrorMessage("tenant registry poisoned".into()))
+    }
+}
+
+impl TenantRegistry for MemoryTenantRegistry {
+    async fn find(&self, id: &str) -> Result<Option<Tenant>> {
+        Ok(self.tenants()?.get(id).cloned())
+    }
+}
+
+/// Settings for the [`tenant`] middleware.
+pub struct TenantResolver<R = MemoryTenantRegistry> {
+    registry: Arc<R>,
+    sources: Vec<TenantSource>,
+    token_claim: String,
+}
+
+impl<R> Clone for TenantResolver<R> {
+    fn clone(&self) -> Self {
+        TenantResolver {
+            registry: self.registry.clone(),
+            sources: self.sources.clone(),
+            token_claim: self.token_claim.clone(),
+        }
+    }
+}
+
+impl<R> TenantResolver<R>
+where
+    R: TenantRegistry,
+{
+    /// Try `sources` in order; the first one yielding an id wins.
+    pub fn new(registry: Arc<R>, sources: Vec<TenantSource>) -> Self {
+        TenantResolver {
+            registry,
+            sources,
+            token_claim: String::from("tenant_id"),
+        }
+    }
+
+    /// Claim of `AuthUser.ext` the resolved tenant must match, `tenant_id` by default.
+    pub fn with_token_claim(mut self, claim: impl Into<String>) -> Self {
+        self.token_claim = claim.into();
+        self
+    }
+
+    fn tenant_id(&self, req: &Request) -> Option<String> {
+        self.sources.iter().find_map(|source| match source {
+            TenantSource::Subdomain { base_domain } => subdomain(req, base_domain),
+            TenantSource::Header { name } => req
+                .headers()
+                .get(name.as_str())
+                .and_then(|v| v.to_str().ok())
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(str::to_string),
+            #[cfg(feature = "jwt")]
+            TenantSource::Claim { name } => token_claim(req, name),
+        })
+    }
+}
+
+/// Resolve the request's [`Tenant`] and store it as an extension.
+///
+/// Fails with a `CommonError`: `400` when no source yields an id, `404` for ids unknown to the
+/// registry, and `403` when the request is authenticated and the token's tenant claim is missing
+/// or names another tenant. Add the layer inside the auth middleware so the token is visible.
+///
+/// ```rust,ignore
+/// let resolver = TenantResolver::new(
+///     registry,
+///     vec![
+///         TenantSource::Subdomain { base_domain: "example.com".into() },
+///         TenantSource::Header { name: "x-tenant-id".into() },
+///     ],
+/// );
+/// let app = Router::new()
+///     .route("/orders", get(list_orders))
+///     .layer(middleware::from_fn_with_state(resolver, tenant::<MemoryTenantRegistry>))
+///     .layer(middleware::from_fn(auth::<Jwt>))
+///     .layer(Extension(jwt_verifier));
+/// ```
+pub async fn tenant<R>(
+    State(resolver): State<TenantResolver<R>>,
+    mut req: Request,
+    next: Next,
+) -> Response
+where
+    R: TenantRegistry,
+{
+    let Some(id) = resolver.tenant_id(&req) else {
+        return api_error(StatusCode::BAD_REQUEST, "missing tenant").into_response();
+    };
+    let tenant = match resolver.registry.find(&id).await {
+        Ok(Some(tenant)) => tenant,
+        Ok(None) => return api_error(StatusCode::NOT_FOUND, "unknown tenant").into_response(),
+        Err(e) => {
+            tracing::warn!(error = %e, "tenant registry failed");
+            return api_error(StatusCode::SERVICE_UNAVAILABLE, "tenant lookup failed")
+                .into_response();
+        }
+    };
+    #[cfg(feature = "jwt")]
+    if req
+        .extensions()
+        .get::<crate::middleware::auth_mw::AuthUser>()
+        .is_some()
+        && token_claim(&req, &resolver.token_claim).as_deref() != Some(tenant.id.as_str())
+    {
+        return api_error(
+            StatusCode::FORBIDDEN,
+            "token does not belong to this tenant",
+        )
+        .into_response();
+    }
+    req.extensions_mut().insert(tenant);
+    next.run(req).await
+}
+
+fn subdomain(req: &Request, base_domain: &str) -> Option<String> {
+    let host = req
+        .headers()
+        .get(header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .or_else(|| req.uri().host())?;
+    let host = host.split(':').next()?.to_ascii_lowercase();
+    let label = host
+        .strip_suffix(&base_domain.to_ascii_lowercase())?
+        .strip_suffix('.')?;
+    (!label.is_empty() && !label.contains('.')).then(|| label.to_string())
+}
+
+#[cfg(feature = "jwt")]
+fn token_claim(req: &Request, name: &str) -> Option<String> {
+    req.extensions()
+        .get::<crate::middleware::auth_mw::AuthUser>()?
+        .ext
+        .as_ref()?
+        .get(name)?
+        .as_str()
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        Router,
+        body::{Body, to_bytes},
+        middleware,
+        routing::get,
+    };
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn app(sources: Vec<TenantSource>) -> Router {
+        let registry = Arc::new(MemoryTenantRegistry::new([
+            Tenant::new("acme").with_data(json!({"plan": "pro"})),
+            Tenant::new("globex"),
+        ]));
+        Router::new()
+            .route("/whoami", get(|tenant: Tenant| async move { tenant.id }))
+            .layer(middleware::from_fn_with_state(
+                TenantResolver::new(registry, sources),
+                tenant::<MemoryTenantRegistry>,
+            ))
+    }
+
+    async fn send(app: Router, request: Request) -> (StatusCode, String) {
+        let response = app.oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_resolves_subdomain_then_header() {
+        let app = app(vec![
+            TenantSource::Subdomain {
+                base_domain: String::from("example.com"),
+            },
+            TenantSource::Header {
+                name: String::from("x-tenant-id"),
+            },
+        ]);
+        let request = |host: &str, header: Option<&str>| {
+            let mut request = Request::get("/whoami").header(header::HOST, host);
+            if let Some(tenant) = header {
+                request = request.header("x-tenant-id", tenant);
+            }
+            request.body(Body::empty()).unwrap()
+        };
+
+        let cases = [
+            (
+                request("acme.example.com:8080", None),
+                StatusCode::OK,
+                "acme",
+            ),
+            (
+                request("example.com", Some("globex")),
+                StatusCode::OK,
+                "globex",
+            ),
+            (
+                request("a.b.example.com", None),
+                StatusCode::BAD_REQUEST,
+                "missing tenant",
+            ),
+            (
+                request("initech.example.com", None),
+                StatusCode::NOT_FOUND,
+                "unknown tenant",
+            ),
+        ];
+        for (request, status, body) in cases {
+            let (got_status, got_body) = send(app.clone(), request).await;
+            assert_eq!(got_status, status);
+            assert!(got_body.contains(body), "{got_body}");
+        }
+    }
+
+    #[cfg(feature = "jwt")]
+    #[tokio::test]
+    async fn test_token_claim_must_match() {
+        use crate::middleware::auth_mw::{AuthUser, Credential};
+
+        let app = |ext: Value| {
+            app(vec![TenantSource::Header {
+                name: String::from("x-tenant-id"),
+            }])
+            .layer(middleware::from_fn(move |mut req: Request, next: Next| {
+                let user = AuthUser {
+                    user_id: String::from("user-1"),
+                    ext: Some(ext.clone()),
+                    credential: Credential::Jwt,
+                };
+                async move {
+                    req.extensions_mut().insert(user);
+                    next.run(req).await
+                }
+            }))
+        };
+        let request = || {
+            Request::get("/whoami")
+                .header("x-tenant-id", "acme")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let (status, body) = send(app(json!({"tenant_id": "acme"})), request()).await;
+        assert_eq!((status, body.as_str()), (StatusCode::OK, "acme"));
+
+        for ext in [json!({"tenant_id": "globex"}), json!({})] {
+            let (status, body) = send(app(ext), request()).await;
+            assert_eq!(status, StatusCode::FORBIDDEN);
+            assert!(body.contains("\"code\":403"), "{body}");
+        }
+    }
+}
@@ -0,0 +1,345 @@
+use axum::{
+    Router,
+    http::header,
+    response::{Html, IntoResponse},
+    routing::get,
+};
+use serde::Deserialize;
+use utoipa::{
+    IntoParams, Modify, PartialSchema, ToSchema,
+    openapi::{
+        OpenApi, Required, Type,
+        path::{Parameter, ParameterBuilder, ParameterIn},
+        schema::ObjectBuilder,
+        security::{Http, HttpAuthScheme, SecurityScheme},
+    },
+};
+
+use crate::{
+    error::{Error, Result},
+    pagination::PageQuery,
+    response::{CommonError, Empty},
+};
+
+/// Name of the security scheme registered by [`BearerAuth`].
+///
+/// Reference it from handlers with `security(("bearer_auth" = []))`.
+pub const BEARER_AUTH: &str = "bearer_auth";
+
+/// Registers the JWT bearer scheme used by the auth middleware.
+///
+/// ```rust,ignore
+/// #[derive(OpenApi)]
+/// #[openapi(paths(get_user), modifiers(&BearerAuth))]
+/// struct ApiDoc;
+/// ```
+pub struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        let mut scheme = Http::new(HttpAuthScheme::Bearer);
+        scheme.bearer_format = Some("JWT".to_string());
+        components.add_security_scheme(BEARER_AUTH, SecurityScheme::Http(scheme));
+    }
+}
+
+/// Add the envelope schemas shared by every route and the [`BearerAuth`] scheme.
+///
+/// Envelopes referenced from `#[utoipa::path]` responses, such as `CommonResponse<User>`, are
+/// collected by utoipa itself (as `CommonResponse_User`); this registers the error and empty
+/// bodies even when no handler names them explicitly.
+pub fn with_common_components(mut openapi: OpenApi) -> OpenApi {
+    BearerAuth.modify(&mut openapi);
+    let components = openapi.components.get_or_insert_with(Default::default);
+    components.schemas.extend([
+        (CommonError::name().into_owned(), CommonError::schema()),
+        (Empty::name().into_owned(), Empty::schema()),
+    ]);
+    #[cfg(feature = "validation")]
+    components.schemas.insert(
+        crate::extract::FieldError::name().into_owned(),
+        crate::extract::FieldError::schema(),
+    );
+    openapi
+}
+
+/// Interactive documentation page served next to the document.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DocsUi {
+    #[default]
+    Swagger,
+    Redoc,
+    /// Serve the JSON document only.
+    None,
+}
+
+/// Where the OpenAPI document and its UI are mounted.
+#[derive(Debug, Clone, Deserialize)]
+pub struct OpenApiCfg {
+    #[serde(default = "default_spec_path")]
+    pub spec_path: String,
+    #[serde(default = "default_ui_path")]
+    pub ui_path: String,
+    #[serde(default)]
+    pub ui: DocsUi,
+}
+
+fn default_spec_path() -> String {
+    String::from("/openapi.json")
+}
+
+fn default_ui_path() -> String {
+    String::from("/docs")
+}
+
+impl OpenApiCfg {
+    fn validate(&self) -> Result<()> {
+        for (name, path) in [("spec_path", &self.spec_path), ("ui_path", &self.ui_path)] {
+            if !path.starts_with('/') {
+                return Err(Error::ErrorMessage(
+                    format!("openapi {name} must start with '/'").into(),
+                ));
+            }
+        }
+        if self.ui != DocsUi::None && self.ui_path == self.spec_path {
+            return Err(Error::ErrorMessage(
+                "openapi ui_path must differ from spec_path".into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Default for OpenApiCfg {
+    fn default() -> Self {
+        OpenApiCfg {
+            spec_path: default_spec_path(),
+            ui_path: default_ui_path(),
+            ui: DocsUi::default(),
+        }
+    }
+}
+
+/// Routes serving `openapi` as JSON and, unless disabled, a Swagger UI or Redoc page.
+///
+/// Fails when a path does not start with `/`, or when the UI would be mounted on the document's
+/// own path.
+///
+/// ```rust,ignore
+/// let doc = with_common_components(ApiDoc::openapi());
+/// let app = Router::new()
+///     .route("/users/{id}", get(get_user))
+///     .merge(openapi::router(doc, &OpenApiCfg::default())?);
+/// ```
+pub fn router<S>(openapi: OpenApi, cfg: &OpenApiCfg) -> Result<Router<S>>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    cfg.validate()?;
+    let spec = openapi
+        .to_json()
+        .map_err(|e| Error::ErrorMessage(format!("failed to serialize OpenAPI: {e}").into()))?;
+    let mut router = Router::new().route(
+        &cfg.spec_path,
+        get(move || async move { ([(header::CONTENT_TYPE, "application/json")], spec) }),
+    );
+    if let Some(page) = ui_page(cfg) {
+        router = router.route(
+            &cfg.ui_path,
+            get(move || async move { page.into_response() }),
+        );
+    }
+    Ok(router)
+}
+
+fn ui_page(cfg: &OpenApiCfg) -> Option<Html<String>> {
+    // JSON-encode the URL for the inline script, and keep `</` from closing the tag.
+    let url = serde_json::to_string(&cfg.spec_path)
+        .unwrap_or_default()
+        .replace("</", "<\\/");
+    // Versions are pinned so a new UI release cannot change the page unannounced.
+    let body = match cfg.ui {
+        DocsUi::Swagger => format!(
+            r##"<link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5.17.14/swagger-ui.css">
+<div id="swagger-ui"></div>
+<script src="https://unpkg.com/swagger-ui-dist@5.17.14/swagger-ui-bundle.js"></script>
+<script>window.ui = SwaggerUIBundle({{ url: {url}, dom_id: "#swagger-ui" }});</script>"##
+        ),
+        DocsUi::Redoc => format!(
+            r#"<div id="redoc"></div>
+<script src="https://unpkg.com/redoc@2.1.5/bundles/redoc.standalone.js"></script>
+<script>Redoc.init({url}, {{}}, document.getElementById("redoc"));</script>"#
+        ),
+        DocsUi::None => return None,
+    };
+    Some(Html(format!(
+        "<!doctype html>\n<html>\n<head><meta charset=\"utf-8\"><title>API \
+         documentation</title></head>\n<body>\n{body}\n</body>\n</html>\n"
+    )))
+}
+
+impl IntoParams for PageQuery {
+    fn into_params(parameter_in_provider: impl Fn() -> Option<ParameterIn>) -> Vec<Parameter> {
+        let parameter_in = parameter_in_provider().unwrap_or(ParameterIn::Query);
+        let param = |name: &str, description: &str, schema: ObjectBuilder| {
+            ParameterBuilder::new()
+                .name(name)
+                .parameter_in(parameter_in.clone())
+                .required(Required::False)
+                .description(Some(description))
+                .schema(Some(schema))
+                .build()
+        };
+        vec![
+            param(
+                "limit",
+                "Page size",
+                ObjectBuilder::new()
+                    .schema_type(Type::Integer)
+                    .minimum(Some(1)),
+            ),
+            param(
+                "offset",
+                "Items to skip, for offset pagination",
+                ObjectBuilder::new()
+                    .schema_type(Type::Integer)
+                    .minimum(Some(0)),
+            ),
+            param(
+                "cursor",
+                "Opaque `next_cursor` from the previous page",
+                ObjectBuilder::new().schema_type(Type::String),
+            ),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::{Body, to_bytes},
+        http::{Request, StatusCode},
+    };
+    use serde::Serialize;
+    use serde_json::Value;
+    use tower::ServiceExt;
+    use utoipa::OpenApi as _;
+
+    use super::*;
+    use crate::{pagination::Paginated, response::CommonResponse};
+
+    #[derive(Serialize, ToSchema)]
+    struct User {
+        id: u64,
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/users",
+        params(PageQuery),
+        responses(
+            (status = 200, body = CommonResponse<Paginated<User>>),
+            (status = 401, body = CommonError),
+        ),
+        security(("bearer_auth" = []))
+    )]
+    #[allow(dead_code)]
+    async fn list_users() {}
+
+    #[derive(utoipa::OpenApi)]
+    #[openapi(paths(list_users))]
+    struct ApiDoc;
+
+    fn document() -> Value {
+        serde_json::to_value(with_common_components(ApiDoc::openapi())).unwrap()
+    }
+
+    #[test]
+    fn test_document_describes_envelopes() {
+        let doc = document();
+        assert!(doc["openapi"].as_str().unwrap().starts_with("3.1"));
+        assert_eq!(
+            doc["components"]["securitySchemes"][BEARER_AUTH]["scheme"],
+            "bearer"
+        );
+        assert!(doc["components"]["schemas"]["CommonError"].is_object());
+
+        let operation = &doc["paths"]["/users"]["get"];
+        let names: Vec<&str> = operation["parameters"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| p["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["limit", "offset", "cursor"]);
+
+        assert_eq!(
+            operation["responses"]["200"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/CommonResponse_Paginated_User"
+        );
+        let envelope = &doc["components"]["schemas"]["CommonResponse_Paginated_User"];
+        assert!(envelope["properties"]["code"].is_object());
+        assert!(
+            envelope["properties"]["data"]["properties"]["next_cursor"].is_object(),
+            "{envelope}"
+        );
+        assert_eq!(
+            operation["responses"]["401"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/CommonError"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_router_serves_document_and_ui() {
+        let cfg = OpenApiCfg {
+            spec_path: "/api-docs/openapi.json".to_string(),
+            ui_path: "/api-docs".to_string(),
+            ui: DocsUi::Redoc,
+        };
+        let app: Router = router(ApiDoc::openapi(), &cfg).unwrap();
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::get("/api-docs/openapi.json")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let doc: Value = serde_json::from_slice(&body).unwrap();
+        assert!(doc["paths"]["/users"].is_object());
+
+        let response = app
+            .oneshot(Request::get("/api-docs").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let page = String::from_utf8(body.to_vec()).unwrap();
+        assert!(page.contains("Redoc.init(\"/api-docs/openapi.json\""));
+    }
+
+    #[test]
+    fn test_router_rejects_conflicting_paths() {
+        let same = OpenApiCfg {
+            spec_path: "/docs".to_string(),
+            ..OpenApiCfg::default()
+        };
+        assert!(router::<()>(ApiDoc::openapi(), &same).is_err());
+        let relative = OpenApiCfg {
+            ui_path: "docs".to_string(),
+            ..OpenApiCfg::default()
+        };
+        assert!(router::<()>(ApiDoc::openapi(), &relative).is_err());
+
+        let spec_only = OpenApiCfg {
+            ui: DocsUi::None,
+            ..same
+        };
+        assert!(router::<()>(ApiDoc::openapi(), &spec_only).is_ok());
+    }
+}
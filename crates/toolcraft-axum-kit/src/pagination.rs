@@ -0,0 +1,299 @@
+use axum::{
+    extract::{FromRequestParts, Query},
+    http::{StatusCode, request::Parts},
+};
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+use hmac::{Hmac, KeyInit, Mac};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use sha2::Sha256;
+
+use crate::{
+    error::{Error, Result},
+    response::{ApiError, api_error},
+};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// One page of a list endpoint, returned as `CommonResponse<Paginated<T>>`.
+///
+/// Offset pages carry `offset` and usually `total`; cursor pages carry `next_cursor`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Paginated<T> {
+    pub items: Vec<T>,
+    pub limit: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offset: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<u64>,
+    #[serde(default)]
+    pub next_cursor: Option<String>,
+    pub has_more: bool,
+}
+
+impl<T> Paginated<T> {
+    /// Build an offset/limit page. `has_more` is derived from `total`.
+    pub fn offset(items: Vec<T>, page: &PageQuery, total: u64) -> Self {
+        let has_more = page.offset.saturating_add(items.len() as u64) < total;
+        Paginated {
+            items,
+            limit: page.limit,
+            offset: Some(page.offset),
+            total: Some(total),
+            next_cursor: None,
+            has_more,
+        }
+    }
+
+    /// Build a cursor page. `has_more` is true when there is a next cursor.
+    pub fn cursor(items: Vec<T>, page: &PageQuery, next_cursor: Option<String>) -> Self {
+        Paginated {
+            items,
+            limit: page.limit,
+            offset: None,
+            total: None,
+            has_more: next_cursor.is_some(),
+            next_cursor,
+        }
+    }
+
+    /// Set the total count, for cursor pages that can afford to compute it.
+    pub fn with_total(mut self, total: u64) -> Self {
+        self.total = Some(total);
+        self
+    }
+}
+
+/// Page size bounds applied by [`PageQuery`].
+///
+/// Install with `Extension(PageLimits { .. })`; [`PageLimits::default`] is used otherwise.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct PageLimits {
+    pub default_limit: u32,
+    pub max_limit: u32,
+}
+
+impl Default for PageLimits {
+    fn default() -> Self {
+        PageLimits {
+            default_limit: 20,
+            max_limit: 100,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RawPageQuery {
+    limit: Option<u32>,
+    offset: Option<u64>,
+    cursor: Option<String>,
+}
+
+/// Query extractor for `?limit=&offset=` or `?limit=&cursor=`.
+///
+/// Rejects a zero or oversized `limit`, and requests that send both `offset` and `cursor`.
+#[derive(Debug, Clone)]
+pub struct PageQuery {
+    pub limit: u32,
+    pub offset: u64,
+    pub cursor: Option<String>,
+}
+
+impl PageQuery {
+    /// Decode and verify the cursor, if any. A tampered cursor is a `400 Bad Request`.
+    pub fn decode_cursor<C>(&self, codec: &CursorCodec) -> Result<Option<C>, ApiError>
+    where
+        C: DeserializeOwned,
+    {
+        self.cursor
+            .as_deref()
+            .map(|cursor| codec.decode(cursor))
+            .transpose()
+            .map_err(|_| api_error(StatusCode::BAD_REQUEST, "invalid cursor"))
+    }
+}
+
+impl<S> FromRequestParts<S> for PageQuery
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let limits = parts
+            .extensions
+            .get::<PageLimits>()
+            .copied()
+            .unwrap_or_default();
+        let Query(raw) = Query::<RawPageQuery>::from_request_parts(parts, state)
+            .await
+            .map_err(|e| api_error(StatusCode::BAD_REQUEST, e.body_text()))?;
+
+        let limit = raw.limit.unwrap_or(limits.default_limit);
+        if limit == 0 || limit > limits.max_limit {
+            return Err(api_error(
+                StatusCode::BAD_REQUEST,
+                format!("limit must be between 1 and {}", limits.max_limit),
+            ));
+        }
+        if raw.offset.is_some() && raw.cursor.is_some() {
+            return Err(api_error(
+                StatusCode::BAD_REQUEST,
+                "offset and cursor cannot be combined",
+            ));
+        }
+
+        Ok(PageQuery {
+            limit,
+            offset: raw.offset.unwrap_or(0),
+            cursor: raw.cursor.filter(|c| !c.is_empty()),
+        })
+    }
+}
+
+/// Encodes cursor state as an opaque, HMAC-signed token.
+///
+/// The token is `base64url(json).base64url(hmac_sha256(json))`, so clients can round-trip it but
+/// any modification fails [`CursorCodec::decode`].
+#[derive(Clone)]
+pub struct CursorCodec {
+    key: Vec<u8>,
+}
+
+impl CursorCodec {
+    pub fn new(secret: impl AsRef<[u8]>) -> Self {
+        CursorCodec {
+            key: secret.as_ref().to_vec(),
+        }
+    }
+
+    pub fn encode<C>(&self, cursor: &C) -> Result<String>
+    where
+        C: Serialize,
+    {
+        let payload = serde_json::to_vec(cursor)
+            .map_err(|e| Error::ErrorMessage(format!("cursor encode error: {e}").into()))?;
+        let signature = self.mac(&payload).finalize().into_bytes();
+        Ok(format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(&payload),
+            URL_SAFE_NO_PAD.encode(signature)
+        ))
+    }
+
+    pub fn decode<C>(&self, token: &str) -> Result<C>
+    where
+        C: DeserializeOwned,
+    {
+        let invalid = || Error::ErrorMessage("invalid cursor".into());
+        let (payload, signature) = token.split_once('.').ok_or_else(invalid)?;
+        let payload = URL_SAFE_NO_PAD.decode(payload).map_err(|_| invalid())?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| invalid())?;
+        self.mac(&payload)
+            .verify_slice(&signature)
+            .map_err(|_| invalid())?;
+        serde_json::from_slice(&payload).map_err(|_| invalid())
+    }
+
+    fn mac(&self, payload: &[u8]) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC can take key of any size");
+        mac.update(payload);
+        mac
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        Extension, Router,
+        body::{Body, to_bytes},
+        http::Request,
+        routing::get,
+    };
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Cursor {
+        after_id: u64,
+    }
+
+    #[test]
+    fn test_cursor_round_trip() {
+        let codec = CursorCodec::new("secret");
+        let token = codec.encode(&Cursor { after_id: 42 }).unwrap();
+        let decoded: Cursor = codec.decode(&token).unwrap();
+        assert_eq!(decoded, Cursor { after_id: 42 });
+    }
+
+    #[test]
+    fn test_cursor_rejects_tampering() {
+        let codec = CursorCodec::new("secret");
+        let token = codec.encode(&Cursor { after_id: 42 }).unwrap();
+        let (_, signature) = token.split_once('.').unwrap();
+        let forged = format!(
+            "{}.{signature}",
+            URL_SAFE_NO_PAD.encode(br#"{"after_id":1}"#)
+        );
+        assert!(codec.decode::<Cursor>(&forged).is_err());
+        assert!(CursorCodec::new("other").decode::<Cursor>(&token).is_err());
+    }
+
+    #[test]
+    fn test_offset_page_has_more() {
+        let page = PageQuery {
+            limit: 2,
+            offset: 2,
+            cursor: None,
+        };
+        assert!(Paginated::offset(vec![3, 4], &page, 5).has_more);
+        assert!(!Paginated::offset(vec![3, 4], &page, 4).has_more);
+
+        let page = PageQuery {
+            limit: 2,
+            offset: u64::MAX,
+            cursor: None,
+        };
+        assert!(!Paginated::offset(vec![3, 4], &page, u64::MAX).has_more);
+    }
+
+    async fn list(page: PageQuery) -> String {
+        format!("{}:{}", page.limit, page.offset)
+    }
+
+    async fn call(uri: &str) -> (StatusCode, Vec<u8>) {
+        let app = Router::new()
+            .route("/items", get(list))
+            .layer(Extension(PageLimits {
+                default_limit: 10,
+                max_limit: 50,
+            }));
+        let response = app
+            .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, body.to_vec())
+    }
+
+    #[tokio::test]
+    async fn test_page_query_limits() {
+        assert_eq!(call("/items").await, (StatusCode::OK, b"10:0".to_vec()));
+        assert_eq!(
+            call("/items?limit=50&offset=100").await,
+            (StatusCode::OK, b"50:100".to_vec())
+        );
+
+        let (status, body) = call("/items?limit=51").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], 400);
+        assert_eq!(body["message"], "limit must be between 1 and 50");
+
+        let (status, _) = call("/items?offset=1&cursor=abc").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,110 @@
+use axum::{
+    Json,
+    http::{HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::response::CommonError;
+
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// RFC 7807 Problem Details body.
+///
+/// Extension members are flattened into the top-level object, as required by the RFC.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProblemDetails {
+    #[serde(rename = "type", default = "about_blank")]
+    pub type_: String,
+    pub title: String,
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    #[serde(flatten)]
+    pub extensions: Map<String, Value>,
+}
+
+fn about_blank() -> String {
+    String::from("about:blank")
+}
+
+impl ProblemDetails {
+    /// Create a problem of type `about:blank` titled with the status reason phrase.
+    pub fn new(status: StatusCode) -> Self {
+        ProblemDetails {
+            type_: about_blank(),
+            title: status.canonical_reason().unwrap_or_default().to_string(),
+            status: status.as_u16(),
+            detail: None,
+            instance: None,
+            extensions: Map::new(),
+        }
+    }
+
+    /// Build a problem from a `CommonError` body and the HTTP status it was sent with.
+    ///
+    /// The error message becomes `detail`; `code`, `data` and `request_id` are kept as extension
+    /// members.
+    pub fn from_common_error(status: StatusCode, error: &CommonError) -> Self {
+        let mut problem = Self::new(status)
+            .with_detail(error.message.clone())
+            .with_extension("code", error.code);
+        if let Some(data) = &error.data {
+            problem = problem.with_extension("data", data.clone());
+        }
+        if let Some(request_id) = &error.request_id {
+            problem = problem.with_extension("request_id", request_id.clone());
+        }
+        problem
+    }
+
+    pub fn with_type(mut self, type_: impl Into<String>) -> Self {
+        self.type_ = type_.into();
+        self
+    }
+
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    pub fn with_instance(mut self, instance: impl Into<String>) -> Self {
+        self.instance = Some(instance.into());
+        self
+    }
+
+    /// Add an extension member. Reserved member names are ignored.
+    pub fn with_extension(mut self, key: impl Into<String>, value: impl Into<Value>) -> Self {
+        let key = key.into();
+        if !matches!(
+            key.as_str(),
+            "type" | "title" | "status" | "detail" | "instance"
+        ) {
+            self.extensions.insert(key, value.into());
+        }
+        self
+    }
+
+    pub fn status_code(&self) -> StatusCode {
+        StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+}
+
+impl IntoResponse for ProblemDetails {
+    fn into_response(self) -> Response {
+        let status = self.status_code();
+        let mut response = (status, Json(self)).into_response();
+        response
+            .headers_mut()
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON));
+        response
+    }
+}
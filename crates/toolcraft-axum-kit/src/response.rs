@@ -1,9 +1,16 @@
 use core::str;
 
-use axum::{Json, http::StatusCode};
-use serde::Serialize;
+use axum::{
+    Json,
+    body::{Body, to_bytes},
+    http::{StatusCode, header, response::Parts},
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-#[derive(Debug, Serialize, Default, Clone)]
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct Empty;
 
 pub type CommonOk = CommonResponse<Empty>;
@@ -29,7 +36,8 @@ where
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct CommonResponse<T>
 where
     T: Serialize,
@@ -61,16 +69,27 @@ where
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct CommonError {
     pub code: i16,
     pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
 }
 
 impl CommonError {
     pub fn to_json(self) -> Json<Self> {
         Json(self)
     }
+
+    /// Attach structured details, such as per-field validation errors.
+    pub fn with_data(mut self, data: impl Serialize) -> Self {
+        self.data = serde_json::to_value(data).ok();
+        self
+    }
 }
 
 impl From<(i16, &str)> for CommonError {
@@ -78,8 +97,65 @@ impl From<(i16, &str)> for CommonError {
         CommonError {
             code: value.0,
             message: value.1.to_string(),
+            data: None,
+            request_id: None,
         }
     }
 }
 
+impl From<ApiError> for CommonError {
+    fn from((_, Json(error)): ApiError) -> Self {
+        error
+    }
+}
+
+impl From<crate::error::Error> for CommonError {
+    fn from(error: crate::error::Error) -> Self {
+        CommonError::from((500, error.to_string().as_str()))
+    }
+}
+
 pub type ResponseResult<T> = core::result::Result<Json<CommonResponse<T>>, ApiError>;
+
+/// Build an `ApiError` whose `code` mirrors the HTTP status.
+pub(crate) fn api_error(status: StatusCode, message: impl Into<String>) -> ApiError {
+    (
+        status,
+        Json(CommonError {
+            code: status.as_u16() as i16,
+            message: message.into(),
+            data: None,
+            request_id: None,
+        }),
+    )
+}
+
+/// Split an error response carrying a JSON `CommonError` body into its parts and the error.
+///
+/// Responses that are not errors, are not JSON, or whose body is not a `CommonError` are handed
+/// back unchanged in `Err`.
+pub(crate) async fn take_common_error(
+    response: Response,
+) -> core::result::Result<(Parts, CommonError), Response> {
+    let status = response.status();
+    if !(status.is_client_error() || status.is_server_error()) || !is_json(&response) {
+        return Err(response);
+    }
+    let (parts, body) = response.into_parts();
+    let bytes = match to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(_) => return Err(Response::from_parts(parts, Body::empty())),
+    };
+    match serde_json::from_slice::<CommonError>(&bytes) {
+        Ok(error) => Ok((parts, error)),
+        Err(_) => Err(Response::from_parts(parts, Body::from(bytes))),
+    }
+}
+
+fn is_json(response: &Response) -> bool {
+    response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"))
+}
@@ -0,0 +1,460 @@
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
+
+use axum::{
+    Router,
+    body::Body,
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, Method, StatusCode, Uri, header},
+    response::{IntoResponse, Response},
+};
+use sha2::{Digest, Sha256};
+use tower_http::services::ServeDir;
+
+use crate::response::api_error;
+
+/// A file compiled into the binary, usually with `include_bytes!`.
+///
+/// Precompressed variants are picked up when the list also contains `<path>.br` or `<path>.gz`.
+#[derive(Debug, Clone, Copy)]
+pub struct EmbeddedAsset {
+    pub path: &'static str,
+    pub contents: &'static [u8],
+}
+
+struct Embedded {
+    contents: &'static [u8],
+    etag: HeaderValue,
+}
+
+enum Source {
+    Dir(ServeDir),
+    Embedded(HashMap<&'static str, Embedded>),
+}
+
+/// Serve a directory or embedded assets, optionally as a single-page app.
+///
+/// Responses carry a MIME type guessed from the extension, an `ETag` (and `Last-Modified` for
+/// files on disk), and honour `If-None-Match`. Precompressed `.br` and `.gz` siblings are served
+/// to clients that accept them. `index.html` is sent with `Cache-Control: no-cache`, everything
+/// else with the configured policy.
+///
+/// With [`StaticFiles::with_spa_fallback`], unknown paths without a file extension get the index
+/// page, so client-side routes survive a reload. Paths under an excluded prefix (`/api` by default)
+/// never do, and get a `404` `CommonError` instead.
+///
+/// ```rust,ignore
+/// let app = Router::new()
+///     .nest("/api", api)
+///     .fallback_service(
+///         StaticFiles::dir("./admin/dist")
+///             .with_spa_fallback("index.html")
+///             .with_immutable_prefix("/assets")
+///             .router(),
+///     );
+/// ```
+pub struct StaticFiles {
+    source: Source,
+    spa_index: Option<String>,
+    excluded_prefixes: Vec<String>,
+    immutable_prefixes: Vec<String>,
+    cache_control: HeaderValue,
+}
+
+impl StaticFiles {
+    pub fn dir(path: impl Into<PathBuf>) -> Self {
+        let serve_dir = ServeDir::new(path.into())
+            .precompressed_br()
+            .precompressed_gzip();
+        Self::new(Source::Dir(serve_dir))
+    }
+
+    pub fn embedded(assets: &'static [EmbeddedAsset]) -> Self {
+        let assets = assets
+            .iter()
+            .map(|asset| {
+                let embedded = Embedded {
+                    contents: asset.contents,
+                    etag: strong_etag(asset.contents),
+                };
+                (asset.path.trim_start_matches('/'), embedded)
+            })
+            .collect();
+        Self::new(Source::Embedded(assets))
+    }
+
+    fn new(source: Source) -> Self {
+        StaticFiles {
+            source,
+            spa_index: None,
+            excluded_prefixes: vec![String::from("/api")],
+            immutable_prefixes: Vec::new(),
+            cache_control: HeaderValue::from_static("public, max-age=3600"),
+        }
+    }
+
+    /// Serve `index` (relative to the root) for unknown paths without a file extension.
+    pub fn with_spa_fallback(mut self, index: impl Into<String>) -> Self {
+        self.spa_index = Some(index.into().trim_start_matches('/').to_string());
+        self
+    }
+
+    /// Never serve the fallback under `prefix`, so API 404s stay 404s.
+    pub fn with_excluded_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.excluded_prefixes.push(prefix.into());
+        self
+    }
+
+    /// Files under `prefix` have content-hashed names and are cached for a year.
+    pub fn with_immutable_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.immutable_prefixes.push(prefix.into());
+        self
+    }
+
+    /// `Cache-Control` for everything but the index page and immutable files.
+    pub fn with_cache_control(mut self, cache_control: HeaderValue) -> Self {
+        self.cache_control = cache_control;
+        self
+    }
+
+    /// A router answering every path, to mount with `Router::fallback_service` or `nest_service`.
+    pub fn router<S>(self) -> Router<S>
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        Router::new()
+            .fallback(serve_static)
+            .with_state(Arc::new(self))
+    }
+
+    async fn fetch(&self, path: &str, method: &Method, headers: &HeaderMap) -> Response {
+        match &self.source {
+            Source::Dir(serve_dir) => {
+                let mut request = Request::new(Body::empty());
+                *request.method_mut() = method.clone();
+                *request.headers_mut() = headers.clone();
+                *request.uri_mut() = match Uri::try_from(path) {
+                    Ok(uri) => uri,
+                    Err(_) => return not_found(),
+                };
+                match serve_dir.clone().try_call(request).await {
+                    Ok(response) => response.map(Body::new),
+                    Err(e) => {
+                        tracing::warn!(error = %e, path, "failed to serve static file");
+                        api_error(StatusCode::INTERNAL_SERVER_ERROR, "failed to read file")
+                            .into_response()
+                    }
+                }
+            }
+            Source::Embedded(assets) => serve_embedded(assets, path, method, headers),
+        }
+    }
+
+    fn cache_control(&self, path: &str, is_index: bool) -> HeaderValue {
+        if is_index || path.ends_with('/') || path.ends_with("/index.html") {
+            HeaderValue::from_static("no-cache")
+        } else if self
+            .immutable_prefixes
+            .iter()
+            .any(|prefix| under_prefix(path, prefix))
+        {
+            HeaderValue::from_static("public, max-age=31536000, immutable")
+        } else {
+            self.cache_control.clone()
+        }
+    }
+}
+
+async fn serve_static(State(files): State<Arc<StaticFiles>>, req: Request) -> Response {
+    let path = req.uri().path().to_string();
+    if files
+        .excluded_prefixes
+        .iter()
+        .any(|prefix| under_prefix(&path, prefix))
+    {
+        return not_found();
+    }
+    if req.method() != Method::GET && req.method() != Method::HEAD {
+        return api_error(StatusCode::METHOD_NOT_ALLOWED, "method not allowed").into_response();
+    }
+
+    let mut response = files.fetch(&path, req.method(), req.headers()).await;
+    let mut is_index = false;
+    if response.status() == StatusCode::NOT_FOUND
+        && let Some(index) = &files.spa_index
+        && !has_extension(&path)
+    {
+        response = files
+            .fetch(&format!("/{index}"), req.method(), req.headers())
+            .await;
+        is_index = true;
+    }
+    match response.status() {
+        StatusCode::OK | StatusCode::NOT_MODIFIED => {}
+        StatusCode::NOT_FOUND => return not_found(),
+        _ => return response,
+    }
+
+    let headers = response.headers_mut();
+    headers.insert(header::CACHE_CONTROL, files.cache_control(&path, is_index));
+    headers.insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+    if !headers.contains_key(header::ETAG)
+        && let Some(etag) = weak_etag(headers)
+    {
+        headers.insert(header::ETAG, etag);
+    }
+    if let Some(etag) = headers.get(header::ETAG)
+        && if_none_match(req.headers(), etag)
+    {
+        let mut not_modified = Response::new(Body::empty());
+        *not_modified.status_mut() = StatusCode::NOT_MODIFIED;
+        for name in [header::ETAG, header::CACHE_CONTROL, header::VARY] {
+            if let Some(value) = response.headers().get(&name) {
+                not_modified.headers_mut().insert(name, value.clone());
+            }
+        }
+        return not_modified;
+    }
+    response
+}
+
+fn serve_embedded(
+    assets: &HashMap<&'static str, Embedded>,
+    path: &str,
+    method: &Method,
+    headers: &HeaderMap,
+) -> Response {
+    let mut path = path.trim_start_matches('/').to_string();
+    if path.is_empty() || path.ends_with('/') {
+        path.push_str("index.html");
+    }
+    let Some(plain) = assets.get(path.as_str()) else {
+        return not_found();
+    };
+    let accepted = headers
+        .get(header::ACCEPT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    let (asset, encoding) = [("br", "br"), ("gzip", "gz")]
+        .into_iter()
+        .filter(|(encoding, _)| accepts(accepted, encoding))
+        .find_map(|(encoding, extension)| {
+            let asset = assets.get(format!("{path}.{extension}").as_str())?;
+            Some((asset, Some(encoding)))
+        })
+        .unwrap_or((plain, None));
+
+    let mime = mime_guess::from_path(&path).first_or_octet_stream();
+    let body = match *method {
+        Method::HEAD => Body::empty(),
+        _ => Body::from(asset.contents),
+    };
+    let mut response = Response::new(body);
+    let headers = response.headers_mut();
+    if let Ok(content_type) = HeaderValue::from_str(mime.as_ref()) {
+        headers.insert(header::CONTENT_TYPE, content_type);
+    }
+    headers.insert(header::CONTENT_LENGTH, asset.contents.len().into());
+    headers.insert(header::ETAG, asset.etag.clone());
+    if let Some(encoding) = encoding {
+        headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static(encoding));
+    }
+    response
+}
+
+fn not_found() -> Response {
+    api_error(StatusCode::NOT_FOUND, "not found").into_response()
+}
+
+fn under_prefix(path: &str, prefix: &str) -> bool {
+    let prefix = prefix.trim_end_matches('/');
+    path == prefix
+        || path
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+fn has_extension(path: &str) -> bool {
+    path.rsplit('/')
+        .next()
+        .is_some_and(|name| name.contains('.'))
+}
+
+fn accepts(accept_encoding: &str, encoding: &str) -> bool {
+    accept_encoding.split(',').any(|entry| {
+        let mut parts = entry.split(';');
+        let name = parts.next().unwrap_or_default().trim();
+        let rejected = parts.any(|p| matches!(p.trim(), "q=0" | "q=0.0" | "q=0.00" | "q=0.000"));
+        name.eq_ignore_ascii_case(encoding) && !rejected
+    })
+}
+
+fn strong_etag(contents: &[u8]) -> HeaderValue {
+    let digest = Sha256::digest(contents);
+    let hex: String = digest[.. 16].iter().map(|b| format!("{b:02x}")).collect();
+    HeaderValue::from_str(&format!("\"{hex}\"")).expect("etag is a valid header value")
+}
+
+/// `ETag` for files on disk, from their size and modification time.
+fn weak_etag(headers: &HeaderMap) -> Option<HeaderValue> {
+    let length = headers.get(header::CONTENT_LENGTH)?.to_str().ok()?;
+    let modified = headers.get(header::LAST_MODIFIED)?.to_str().ok()?;
+    let digest = Sha256::digest(format!("{length} {modified}"));
+    let hex: String = digest[.. 8].iter().map(|b| format!("{b:02x}")).collect();
+    HeaderValue::from_str(&format!("W/\"{hex}\"")).ok()
+}
+
+fn if_none_match(headers: &HeaderMap, etag: &HeaderValue) -> bool {
+    let Some(condition) = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+    else {
+        return false;
+    };
+    let etag = etag.to_str().unwrap_or_default().trim_start_matches("W/");
+    condition
+        .split(',')
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use axum::{body::to_bytes, routing::get};
+    use serde_json::Value;
+    use tower::ServiceExt;
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn site() -> PathBuf {
+        let root = std::env::temp_dir().join(format!("toolcraft-static-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(root.join("assets")).unwrap();
+        let write =
+            |path: &str, contents: &[u8]| std::fs::write(root.join(path), contents).unwrap();
+        write("index.html", b"<html>app</html>");
+        write("assets/app.js", b"console.log('app')");
+        write("assets/app.js.gz", b"gzipped app");
+        root
+    }
+
+    fn app(root: &Path) -> Router {
+        Router::new()
+            .route("/api/users", get(|| async { "users" }))
+            .fallback_service(
+                StaticFiles::dir(root)
+                    .with_spa_fallback("index.html")
+                    .with_immutable_prefix("/assets")
+                    .router(),
+            )
+    }
+
+    async fn send(
+        app: &Router,
+        uri: &str,
+        headers: &[(&str, &str)],
+    ) -> (StatusCode, HeaderMap, Vec<u8>) {
+        let mut request = Request::get(uri);
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let response = app
+            .clone()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, headers, body.to_vec())
+    }
+
+    #[tokio::test]
+    async fn test_dir_headers_precompression_and_revalidation() {
+        let root = site();
+        let app = app(&root);
+
+        let (status, headers, body) = send(&app, "/assets/app.js", &[]).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, b"console.log('app')");
+        assert_eq!(headers[header::CONTENT_TYPE], "text/javascript");
+        assert_eq!(
+            headers[header::CACHE_CONTROL],
+            "public, max-age=31536000, immutable"
+        );
+        assert!(headers.contains_key(header::LAST_MODIFIED));
+        let etag = headers[header::ETAG].to_str().unwrap().to_string();
+
+        let (status, _, body) = send(&app, "/assets/app.js", &[("if-none-match", &etag)]).await;
+        assert_eq!(status, StatusCode::NOT_MODIFIED);
+        assert!(body.is_empty());
+
+        let (status, headers, body) =
+            send(&app, "/assets/app.js", &[("accept-encoding", "gzip")]).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(body, b"gzipped app");
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_spa_fallback_keeps_api_and_asset_404s() {
+        let root = site();
+        let app = app(&root);
+
+        let (status, headers, body) = send(&app, "/admin/users/42", &[]).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, b"<html>app</html>");
+        assert_eq!(headers[header::CACHE_CONTROL], "no-cache");
+
+        let (status, _, body) = send(&app, "/api/users", &[]).await;
+        assert_eq!((status, body.as_slice()), (StatusCode::OK, &b"users"[..]));
+
+        for uri in ["/api/missing", "/assets/missing.js"] {
+            let (status, _, body) = send(&app, uri, &[]).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{uri}");
+            let error: Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(error["code"], 404);
+        }
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_embedded_assets() {
+        static ASSETS: &[EmbeddedAsset] = &[
+            EmbeddedAsset {
+                path: "index.html",
+                contents: b"<html>embedded</html>",
+            },
+            EmbeddedAsset {
+                path: "style.css",
+                contents: b"body {}",
+            },
+            EmbeddedAsset {
+                path: "style.css.br",
+                contents: b"brotli css",
+            },
+        ];
+        let app = Router::new().fallback_service(
+            StaticFiles::embedded(ASSETS)
+                .with_spa_fallback("index.html")
+                .router(),
+        );
+
+        let (status, headers, body) =
+            send(&app, "/style.css", &[("accept-encoding", "gzip, br")]).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[header::CONTENT_TYPE], "text/css");
+        assert_eq!(headers[header::CONTENT_ENCODING], "br");
+        assert_eq!(body, b"brotli css");
+
+        let (_, headers, body) = send(&app, "/", &[]).await;
+        assert_eq!(body, b"<html>embedded</html>");
+        let etag = headers[header::ETAG].to_str().unwrap().to_string();
+        let (status, _, _) = send(&app, "/settings", &[("if-none-match", &etag)]).await;
+        assert_eq!(status, StatusCode::NOT_MODIFIED);
+    }
+}
@@ -0,0 +1,276 @@
+use std::{convert::Infallible, future::ready, time::Duration};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::FromRequestParts,
+    http::{HeaderName, StatusCode, header, request::Parts},
+    response::{
+        IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+};
+use futures_util::{Stream, StreamExt};
+use serde::Serialize;
+
+use crate::response::CommonError;
+
+pub const LAST_EVENT_ID: HeaderName = HeaderName::from_static("last-event-id");
+
+/// Content type of [`ndjson`] responses.
+pub const NDJSON: &str = "application/x-ndjson";
+
+/// Name of the final event emitted when the source stream fails.
+pub const ERROR_EVENT: &str = "error";
+
+/// A Server-Sent Event whose `data` is `T` serialized as JSON.
+#[derive(Debug, Clone)]
+pub struct SseEvent<T> {
+    pub data: T,
+    pub event: Option<String>,
+    pub id: Option<String>,
+    pub retry: Option<Duration>,
+}
+
+impl<T> SseEvent<T>
+where
+    T: Serialize,
+{
+    pub fn new(data: T) -> Self {
+        SseEvent {
+            data,
+            event: None,
+            id: None,
+            retry: None,
+        }
+    }
+
+    /// Event type, dispatched to `addEventListener(event, ..)` in the browser.
+    pub fn with_event(mut self, event: impl Into<String>) -> Self {
+        self.event = Some(event.into());
+        self
+    }
+
+    /// Event id, sent back by reconnecting clients as [`LastEventId`].
+    pub fn with_id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    pub fn with_retry(mut self, retry: Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    fn into_event(self) -> Event {
+        let mut event = match Event::default().json_data(&self.data) {
+            Ok(event) => event,
+            Err(e) => return error_event(&CommonError::from((500, e.to_string().as_str()))),
+        };
+        if let Some(name) = self.event {
+            event = event.event(single_line(&name));
+        }
+        if let Some(id) = self.id {
+            event = event.id(single_line(&id));
+        }
+        if let Some(retry) = self.retry {
+            event = event.retry(retry);
+        }
+        event
+    }
+}
+
+/// `Last-Event-ID` sent by a reconnecting `EventSource`, to resume the stream after that event.
+///
+/// ```rust,ignore
+/// async fn progress(LastEventId(last): LastEventId) -> impl IntoResponse {
+///     let from = last.and_then(|id| id.parse::<u64>().ok()).map_or(0, |id| id + 1);
+///     sse(job_events(from))
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LastEventId(pub Option<String>);
+
+impl<S> FromRequestParts<S> for LastEventId
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(LastEventId(
+            parts
+                .headers
+                .get(&LAST_EVENT_ID)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string),
+        ))
+    }
+}
+
+/// Stream `SseEvent`s as `text/event-stream`, with keep-alive comments every 15 seconds.
+///
+/// If the stream yields an error, it is sent as a final `error` event whose data is the
+/// `CommonError`, and the stream ends. Override the keep-alive with `.keep_alive(..)` on the
+/// result.
+///
+/// ```rust,ignore
+/// async fn completions(Json(prompt): Json<Prompt>) -> impl IntoResponse {
+///     let tokens = llm.stream(prompt).map(|chunk| chunk.map(|c| SseEvent::new(c).with_event("token")));
+///     sse(tokens)
+/// }
+/// ```
+pub fn sse<St, T, E>(stream: St) -> Sse<impl Stream<Item = Result<Event, Infallible>>>
+where
+    St: Stream<Item = Result<SseEvent<T>, E>> + Send + 'static,
+    T: Serialize + Send + 'static,
+    E: Into<CommonError> + Send + 'static,
+{
+    let events = stop_after_error(stream).map(|item| {
+        Ok(match item {
+            Ok(event) => event.into_event(),
+            Err(error) => error_event(&error),
+        })
+    });
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+/// Stream items as newline-delimited JSON (`application/x-ndjson`).
+///
+/// If the stream yields an error, the `CommonError` is written as the last line.
+pub fn ndjson<St, T, E>(stream: St) -> Response
+where
+    St: Stream<Item = Result<T, E>> + Send + 'static,
+    T: Serialize + Send + 'static,
+    E: Into<CommonError> + Send + 'static,
+{
+    let lines = stop_after_error(stream).map(|item| {
+        let line = match item {
+            Ok(value) => serde_json::to_vec(&value)
+                .unwrap_or_else(|e| json_line(&CommonError::from((500, e.to_string().as_str())))),
+            Err(error) => json_line(&error),
+        };
+        Ok::<_, Infallible>(Bytes::from([line, b"\n".to_vec()].concat()))
+    });
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, NDJSON)],
+        Body::from_stream(lines),
+    )
+        .into_response()
+}
+
+/// Pass items through until the first error, which is converted and ends the stream.
+fn stop_after_error<St, T, E>(stream: St) -> impl Stream<Item = Result<T, CommonError>>
+where
+    St: Stream<Item = Result<T, E>>,
+    E: Into<CommonError>,
+{
+    stream.scan(false, |failed, item| {
+        if *failed {
+            return ready(None);
+        }
+        *failed = item.is_err();
+        ready(Some(item.map_err(Into::into)))
+    })
+}
+
+fn error_event(error: &CommonError) -> Event {
+    Event::default()
+        .event(ERROR_EVENT)
+        .data(String::from_utf8_lossy(&json_line(error)))
+}
+
+fn json_line(error: &CommonError) -> Vec<u8> {
+    serde_json::to_vec(error).unwrap_or_default()
+}
+
+/// Event names and ids must not contain line breaks or NUL, which would corrupt the stream.
+fn single_line(value: &str) -> String {
+    value.replace(['\r', '\n', '\0'], "")
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        Router,
+        body::to_bytes,
+        http::{Request, StatusCode},
+        routing::get,
+    };
+    use futures_util::stream;
+    use serde_json::{Value, json};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    async fn body(app: Router, request: Request<Body>) -> (Response, String) {
+        let response = app.oneshot(request).await.unwrap();
+        let (parts, body) = response.into_parts();
+        let bytes = to_bytes(body, usize::MAX).await.unwrap();
+        (
+            Response::from_parts(parts, Body::empty()),
+            String::from_utf8(bytes.to_vec()).unwrap(),
+        )
+    }
+
+    fn items() -> Vec<Result<u32, CommonError>> {
+        vec![
+            Ok(1),
+            Ok(2),
+            Err(CommonError::from((503, "upstream closed"))),
+            Ok(3),
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_sse_emits_typed_events_and_final_error() {
+        let app = Router::new().route(
+            "/events",
+            get(|LastEventId(last): LastEventId| async move {
+                let start: u32 = last.and_then(|id| id.parse().ok()).unwrap_or(0);
+                sse(stream::iter(items()).map(move |item| {
+                    item.map(|n| {
+                        SseEvent::new(json!({ "n": n + start }))
+                            .with_event("tick")
+                            .with_id(n.to_string())
+                    })
+                }))
+            }),
+        );
+        let request = Request::get("/events")
+            .header(&LAST_EVENT_ID, "10")
+            .body(Body::empty())
+            .unwrap();
+        let (response, body) = body(app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/event-stream"
+        );
+        assert_eq!(
+            body,
+            "data: {\"n\":11}\nevent: tick\nid: 1\n\ndata: {\"n\":12}\nevent: tick\nid: \
+             2\n\nevent: error\ndata: {\"code\":503,\"message\":\"upstream closed\"}\n\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ndjson_ends_with_error_line() {
+        let app = Router::new().route("/export", get(|| async { ndjson(stream::iter(items())) }));
+        let (response, body) =
+            body(app, Request::get("/export").body(Body::empty()).unwrap()).await;
+        assert_eq!(response.headers()[header::CONTENT_TYPE], NDJSON);
+        let lines: Vec<Value> = body
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                json!(1),
+                json!(2),
+                json!({"code": 503, "message": "upstream closed"})
+            ]
+        );
+    }
+}
@@ -0,0 +1,446 @@
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use axum::{
+    Json, Router,
+    body::Bytes,
+    extract::{DefaultBodyLimit, Multipart, State, multipart::MultipartError},
+    http::StatusCode,
+    routing::post,
+};
+use futures_util::Stream;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use toolcraft_s3_kit::{BucketClient, error::Error as S3Error};
+use uuid::Uuid;
+
+use crate::response::{ApiError, IntoCommonResponse, ResponseResult, api_error};
+
+/// Limits and naming for [`S3Upload`].
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct UploadCfg {
+    /// Largest accepted file, in bytes.
+    #[serde(default = "default_max_size")]
+    pub max_size: u64,
+    /// Accepted content types, such as `application/pdf` or `image/*`; empty accepts any.
+    #[serde(default)]
+    pub allowed_content_types: Vec<String>,
+    /// Prepended to generated keys, such as `uploads/`.
+    #[serde(default)]
+    pub key_prefix: String,
+    /// Multipart field holding the file.
+    #[serde(default = "default_field")]
+    pub field: String,
+}
+
+fn default_max_size() -> u64 {
+    10 * 1024 * 1024
+}
+
+fn default_field() -> String {
+    String::from("file")
+}
+
+impl Default for UploadCfg {
+    fn default() -> Self {
+        UploadCfg {
+            max_size: default_max_size(),
+            allowed_content_types: Vec::new(),
+            key_prefix: String::new(),
+            field: default_field(),
+        }
+    }
+}
+
+/// Where and how an upload was stored.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct StoredObject {
+    pub key: String,
+    pub size: u64,
+    pub content_type: String,
+    /// Hex-encoded SHA-256 of the content.
+    pub sha256: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_name: Option<String>,
+}
+
+/// Streams a multipart file field into a bucket without buffering it in memory.
+///
+/// Keys are `{key_prefix}{uuid}{.ext}`, keeping the extension of the uploaded file name.
+///
+/// ```rust,ignore
+/// let upload = S3Upload::new(s3_cfg.build_bucket_client()?, upload_cfg);
+/// let app = Router::new().merge(upload.router("/files"));
+/// ```
+#[derive(Clone)]
+pub struct S3Upload {
+    bucket: BucketClient,
+    cfg: Arc<UploadCfg>,
+}
+
+impl S3Upload {
+    pub fn new(bucket: BucketClient, cfg: UploadCfg) -> Self {
+        S3Upload {
+            bucket,
+            cfg: Arc::new(cfg),
+        }
+    }
+
+    /// A router accepting `POST path` with [`upload_to_s3`].
+    ///
+    /// The default body limit is lifted on this route, since `max_size` is enforced while
+    /// streaming.
+    pub fn router<S>(self, path: &str) -> Router<S>
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        Router::new()
+            .route(path, post(upload_to_s3))
+            .layer(DefaultBodyLimit::disable())
+            .with_state(self)
+    }
+
+    /// Store the configured file field of `multipart`, skipping other fields.
+    ///
+    /// Rejections are `400` for a missing field or a malformed body, `413` above `max_size`,
+    /// `415` for a content type that is not allowed and `502` when the bucket fails.
+    pub async fn store(&self, mut multipart: Multipart) -> Result<StoredObject, ApiError> {
+        let field = loop {
+            match multipart.next_field().await {
+                Ok(Some(field)) if field.name() == Some(self.cfg.field.as_str()) => break field,
+                Ok(Some(_)) => continue,
+                Ok(None) => {
+                    return Err(api_error(
+                        StatusCode::BAD_REQUEST,
+                        format!("missing `{}` field", self.cfg.field),
+                    ));
+                }
+                Err(e) => return Err(api_error(e.status(), e.body_text())),
+            }
+        };
+
+        let content_type = field
+            .content_type()
+            .unwrap_or("application/octet-stream")
+            .to_string();
+        if !self.is_allowed(&content_type) {
+            return Err(api_error(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                format!("content type `{content_type}` is not allowed"),
+            ));
+        }
+        let file_name = field.file_name().map(str::to_string);
+        let key = self.object_key(file_name.as_deref());
+
+        let mut body = Metered::new(field, self.cfg.max_size);
+        let result = self
+            .bucket
+            .upload_stream(&key, &mut body, Some(&content_type))
+            .await;
+        if body.exceeded {
+            return Err(api_error(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!("file exceeds {} bytes", self.cfg.max_size),
+            ));
+        }
+        if let Some(e) = body.read_error {
+            return Err(api_error(e.status(), e.body_text()));
+        }
+        let size = result.map_err(|e| {
+            tracing::error!(error = %e, key, "upload to bucket failed");
+            api_error(StatusCode::BAD_GATEWAY, "failed to store file")
+        })?;
+
+        Ok(StoredObject {
+            key,
+            size,
+            content_type,
+            sha256: body.sha256(),
+            file_name,
+        })
+    }
+
+    fn is_allowed(&self, content_type: &str) -> bool {
+        let content_type = content_type.split(';').next().unwrap_or_default().trim();
+        self.cfg.allowed_content_types.is_empty()
+            || self.cfg.allowed_content_types.iter().any(|allowed| {
+                match allowed.strip_suffix("/*") {
+                    Some(kind) => content_type
+                        .split_once('/')
+                        .is_some_and(|(k, _)| k.eq_ignore_ascii_case(kind)),
+                    None => allowed.eq_ignore_ascii_case(content_type),
+                }
+            })
+    }
+
+    fn object_key(&self, file_name: Option<&str>) -> String {
+        let extension = file_name
+            .and_then(|name| name.rsplit_once('.'))
+            .map(|(_, ext)| ext)
+            .filter(|ext| {
+                !ext.is_empty() && ext.len() <= 16 && ext.bytes().all(|b| b.is_ascii_alphanumeric())
+            })
+            .map(|ext| format!(".{}", ext.to_ascii_lowercase()))
+            .unwrap_or_default();
+        format!("{}{}{extension}", self.cfg.key_prefix, Uuid::new_v4())
+    }
+}
+
+/// Handler storing the upload and answering with its [`StoredObject`].
+pub async fn upload_to_s3(
+    State(upload): State<S3Upload>,
+    multipart: Multipart,
+) -> ResponseResult<StoredObject> {
+    let stored = upload.store(multipart).await?;
+    Ok(Json(stored.into_common_response()))
+}
+
+/// Counts and hashes the chunks of a field, failing once `max` bytes are exceeded.
+struct Metered<S> {
+    inner: S,
+    max: u64,
+    size: u64,
+    hasher: Sha256,
+    exceeded: bool,
+    read_error: Option<MultipartError>,
+}
+
+impl<S> Metered<S> {
+    fn new(inner: S, max: u64) -> Self {
+        Metered {
+            inner,
+            max,
+            size: 0,
+            hasher: Sha256::new(),
+            exceeded: false,
+            read_error: None,
+        }
+    }
+
+    fn sha256(&self) -> String {
+        self.hasher
+            .clone()
+            .finalize()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect()
+    }
+}
+
+impl<S> Stream for Metered<S>
+where
+    S: Stream<Item = Result<Bytes, MultipartError>> + Unpin,
+{
+    type Item = Result<Bytes, S3Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        if this.exceeded || this.read_error.is_some() {
+            return Poll::Ready(None);
+        }
+        match Pin::new(&mut this.inner).poll_next(cx) {
+            Poll::Ready(Some(Ok(chunk))) => {
+                this.size += chunk.len() as u64;
+                if this.size > this.max {
+                    this.exceeded = true;
+                    return Poll::Ready(Some(Err(S3Error::Message("upload too large".into()))));
+                }
+                this.hasher.update(&chunk);
+                Poll::Ready(Some(Ok(chunk)))
+            }
+            Poll::Ready(Some(Err(e))) => {
+                let error = S3Error::Message(e.body_text().into());
+                this.read_error = Some(e);
+                Poll::Ready(Some(Err(error)))
+            }
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use axum::{
+        body::{Body, to_bytes},
+        extract::{Path, Query},
+        http::{Method, Request, header},
+        routing::any,
+    };
+    use serde_json::Value;
+    use toolcraft_s3_kit::{MULTIPART_PART_SIZE, S3Client};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct FakeS3 {
+        objects: HashMap<String, Vec<u8>>,
+        parts: HashMap<String, Vec<Vec<u8>>>,
+    }
+
+    /// Just enough of the S3 API for single PUTs and multipart uploads, without auth.
+    async fn fake_s3(
+        State(state): State<Arc<Mutex<FakeS3>>>,
+        method: Method,
+        Path((_, key)): Path<(String, String)>,
+        Query(query): Query<HashMap<String, String>>,
+        body: Bytes,
+    ) -> (StatusCode, [(header::HeaderName, String); 1], String) {
+        let mut s3 = state.lock().unwrap();
+        let etag = |n: &str| [(header::ETAG, format!("\"{n}\""))];
+        match (method, query.get("uploadId")) {
+            (Method::POST, None) => {
+                s3.parts.insert(key.clone(), Vec::new());
+                let xml = format!(
+                    "<InitiateMultipartUploadResult><UploadId>{key}</UploadId></\
+                     InitiateMultipartUploadResult>"
+                );
+                (StatusCode::OK, etag(""), xml)
+            }
+            (Method::PUT, Some(id)) => {
+                let parts = s3.parts.get_mut(id).unwrap();
+                parts.push(body.to_vec());
+                (
+                    StatusCode::OK,
+                    etag(&parts.len().to_string()),
+                    String::new(),
+                )
+            }
+            (Method::POST, Some(id)) => {
+                let data = s3.parts.remove(id).unwrap().concat();
+                s3.objects.insert(key, data);
+                (
+                    StatusCode::OK,
+                    etag(""),
+                    String::from("<CompleteMultipartUploadResult/>"),
+                )
+            }
+            (Method::DELETE, Some(id)) => {
+                s3.parts.remove(id);
+                (StatusCode::NO_CONTENT, etag(""), String::new())
+            }
+            (Method::PUT, None) => {
+                s3.objects.insert(key, body.to_vec());
+                (StatusCode::OK, etag("single"), String::new())
+            }
+            _ => (StatusCode::METHOD_NOT_ALLOWED, etag(""), String::new()),
+        }
+    }
+
+    async fn setup(cfg: UploadCfg) -> (Router, Arc<Mutex<FakeS3>>) {
+        let state = Arc::new(Mutex::new(FakeS3::default()));
+        let s3 = Router::new()
+            .route("/{bucket}/{*key}", any(fake_s3))
+            .layer(DefaultBodyLimit::disable())
+            .with_state(state.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, s3).await.unwrap() });
+
+        let client = S3Client::new(&format!("http://{addr}"), "ak", "sk", None).unwrap();
+        let bucket = BucketClient::new(Arc::new(client), "files");
+        (S3Upload::new(bucket, cfg).router("/upload"), state)
+    }
+
+    fn multipart(content_type: &str, data: &[u8]) -> Request<Body> {
+        let mut body =
+            b"--XBOUNDARY\r\nContent-Disposition: form-data; name=\"note\"\r\n\r\nhello\r\n"
+                .to_vec();
+        body.extend_from_slice(
+            format!(
+                "--XBOUNDARY\r\nContent-Disposition: form-data; name=\"file\"; \
+                 filename=\"report.PDF\"\r\nContent-Type: {content_type}\r\n\r\n"
+            )
+            .as_bytes(),
+        );
+        body.extend_from_slice(data);
+        body.extend_from_slice(b"\r\n--XBOUNDARY--\r\n");
+        Request::post("/upload")
+            .header(
+                header::CONTENT_TYPE,
+                "multipart/form-data; boundary=XBOUNDARY",
+            )
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    async fn send(app: Router, request: Request<Body>) -> (StatusCode, Value) {
+        let response = app.oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    fn hex_sha256(data: &[u8]) -> String {
+        Sha256::digest(data)
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_small_upload_is_stored() {
+        let cfg = UploadCfg {
+            key_prefix: String::from("docs/"),
+            allowed_content_types: vec![String::from("application/pdf")],
+            ..UploadCfg::default()
+        };
+        let (app, s3) = setup(cfg).await;
+        let (status, body) = send(app, multipart("application/pdf", b"%PDF-1.7")).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        let key = body["data"]["key"].as_str().unwrap();
+        assert!(key.starts_with("docs/") && key.ends_with(".pdf"), "{key}");
+        assert_eq!(body["data"]["size"], 8);
+        assert_eq!(body["data"]["file_name"], "report.PDF");
+        assert_eq!(body["data"]["sha256"], hex_sha256(b"%PDF-1.7"));
+        assert_eq!(s3.lock().unwrap().objects[key], b"%PDF-1.7");
+    }
+
+    #[tokio::test]
+    async fn test_large_upload_uses_multipart() {
+        let data: Vec<u8> = (0 .. MULTIPART_PART_SIZE * 2 + 10)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let cfg = UploadCfg {
+            max_size: data.len() as u64,
+            ..UploadCfg::default()
+        };
+        let (app, s3) = setup(cfg).await;
+        let (status, body) = send(app, multipart("application/octet-stream", &data)).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["data"]["size"], data.len());
+        assert_eq!(body["data"]["sha256"], hex_sha256(&data));
+        let key = body["data"]["key"].as_str().unwrap();
+        let s3 = s3.lock().unwrap();
+        assert!(s3.objects[key] == data);
+        assert!(s3.parts.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_rejects_type_and_size() {
+        let cfg = UploadCfg {
+            max_size: 4,
+            allowed_content_types: vec![String::from("image/*")],
+            ..UploadCfg::default()
+        };
+        let (app, s3) = setup(cfg).await;
+        let (status, body) = send(app.clone(), multipart("application/pdf", b"abc")).await;
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(body["code"], 415);
+
+        let (status, body) = send(app, multipart("image/png", b"too large")).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(body["message"], "file exceeds 4 bytes");
+        assert!(s3.lock().unwrap().objects.is_empty());
+    }
+}
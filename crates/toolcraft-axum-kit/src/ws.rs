@@ -0,0 +1,475 @@
+use std::{collections::HashMap, future::Future, marker::PhantomData, sync::Arc, time::Duration};
+
+use axum::{
+    extract::{
+        FromRequestParts, Query,
+        ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade, close_code},
+    },
+    http::{StatusCode, header, request::Parts},
+    response::Response,
+};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use tokio::time::{Instant, MissedTickBehavior, interval_at, sleep_until, timeout};
+use toolcraft_jwt::AccessTokenVerifier;
+
+use crate::{
+    error::{Error, Result},
+    middleware::auth_mw::{AuthUser, verify_token},
+    response::{ApiError, api_error},
+};
+
+/// Prefix of the `Sec-WebSocket-Protocol` entry carrying the token, as in `bearer.<jwt>`.
+pub const BEARER_PROTOCOL_PREFIX: &str = "bearer.";
+
+/// Where the access token is read from during the upgrade.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum WsTokenSource {
+    /// A query parameter, such as `/ws?access_token=<jwt>`.
+    Query { param: String },
+    /// A `bearer.<jwt>` entry in `Sec-WebSocket-Protocol`. Clients should also offer one of
+    /// [`WsCfg::protocols`], which the server selects in its reply.
+    Subprotocol,
+    /// The first text message after the upgrade, as `{"token": "<jwt>"}`.
+    FirstMessage,
+}
+
+impl Default for WsTokenSource {
+    fn default() -> Self {
+        WsTokenSource::Query {
+            param: String::from("access_token"),
+        }
+    }
+}
+
+/// WebSocket settings, read from an `Extension<WsCfg>` when present.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct WsCfg {
+    #[serde(default)]
+    pub token_source: WsTokenSource,
+    /// Subprotocols the server accepts, in order of preference.
+    #[serde(default)]
+    pub protocols: Vec<String>,
+    #[serde(default = "default_ping_interval_secs")]
+    pub ping_interval_secs: u64,
+    /// Close the socket when nothing, not even a pong, arrives for this long.
+    #[serde(default = "default_idle_timeout_secs")]
+    pub idle_timeout_secs: u64,
+    /// Time allowed for the first message with [`WsTokenSource::FirstMessage`].
+    #[serde(default = "default_auth_timeout_secs")]
+    pub auth_timeout_secs: u64,
+}
+
+fn default_ping_interval_secs() -> u64 {
+    30
+}
+
+fn default_idle_timeout_secs() -> u64 {
+    90
+}
+
+fn default_auth_timeout_secs() -> u64 {
+    10
+}
+
+impl Default for WsCfg {
+    fn default() -> Self {
+        WsCfg {
+            token_source: WsTokenSource::default(),
+            protocols: Vec::new(),
+            ping_interval_secs: default_ping_interval_secs(),
+            idle_timeout_secs: default_idle_timeout_secs(),
+            auth_timeout_secs: default_auth_timeout_secs(),
+        }
+    }
+}
+
+/// WebSocket upgrade that authenticates the client with the `Arc<T>` verifier extension, the same
+/// one [`auth`](crate::middleware::auth_mw::auth) uses.
+///
+/// Query and subprotocol tokens are checked before upgrading, so bad tokens get a `401`
+/// `CommonError`; first-message tokens are checked after, and failures close the socket with a
+/// policy violation.
+///
+/// ```rust,ignore
+/// async fn chat(ws: AuthWsUpgrade<Jwt>) -> Response {
+///     ws.on_upgrade(|user, mut socket: JsonSocket<ClientMsg, ServerMsg>| async move {
+///         while let Some(Ok(msg)) = socket.recv().await {
+///             let _ = socket.send(&reply(&user, msg)).await;
+///         }
+///     })
+/// }
+///
+/// let app = Router::new()
+///     .route("/ws", get(chat))
+///     .layer(Extension(jwt_verifier))
+///     .layer(Extension(ws_cfg));
+/// ```
+pub struct AuthWsUpgrade<T>
+where
+    T: AccessTokenVerifier + 'static,
+{
+    upgrade: WebSocketUpgrade,
+    verifier: Arc<T>,
+    cfg: WsCfg,
+    user: Option<AuthUser>,
+}
+
+impl<T, S> FromRequestParts<S> for AuthWsUpgrade<T>
+where
+    T: AccessTokenVerifier + 'static,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let verifier = parts.extensions.get::<Arc<T>>().cloned().ok_or_else(|| {
+            api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "missing token verifier extension",
+            )
+        })?;
+        let cfg = parts.extensions.get::<WsCfg>().cloned().unwrap_or_default();
+        let upgrade = WebSocketUpgrade::from_request_parts(parts, state)
+            .await
+            .map_err(|e| api_error(e.status(), e.body_text()))?
+            .protocols(cfg.protocols.clone());
+
+        let token = match &cfg.token_source {
+            WsTokenSource::Query { param } => Some(query_token(parts, param)),
+            WsTokenSource::Subprotocol => Some(protocol_token(parts)),
+            WsTokenSource::FirstMessage => None,
+        };
+        let user = match token {
+            Some(token) => {
+                let token = token.ok_or_else(unauthorized)?;
+                Some(verify_token(verifier.as_ref(), &token).map_err(|_| unauthorized())?)
+            }
+            None => None,
+        };
+
+        Ok(AuthWsUpgrade {
+            upgrade,
+            verifier,
+            cfg,
+            user,
+        })
+    }
+}
+
+impl<T> AuthWsUpgrade<T>
+where
+    T: AccessTokenVerifier + 'static,
+{
+    /// Complete the upgrade and run `handler` with the authenticated user and a JSON socket.
+    pub fn on_upgrade<In, Out, F, Fut>(self, handler: F) -> Response
+    where
+        In: DeserializeOwned + Send + 'static,
+        Out: Serialize + Send + 'static,
+        F: FnOnce(AuthUser, JsonSocket<In, Out>) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let AuthWsUpgrade {
+            upgrade,
+            verifier,
+            cfg,
+            user,
+        } = self;
+        upgrade.on_upgrade(move |mut socket| async move {
+            let user = match user {
+                Some(user) => user,
+                None => match first_message_user(&mut socket, verifier.as_ref(), &cfg).await {
+                    Some(user) => user,
+                    None => {
+                        let _ = socket.send(close(close_code::POLICY, "unauthorized")).await;
+                        return;
+                    }
+                },
+            };
+            handler(user, JsonSocket::new(socket, &cfg)).await;
+        })
+    }
+}
+
+fn unauthorized() -> ApiError {
+    api_error(StatusCode::UNAUTHORIZED, "unauthorized")
+}
+
+fn query_token(parts: &Parts, param: &str) -> Option<String> {
+    let Query(mut query) = Query::<HashMap<String, String>>::try_from_uri(&parts.uri).ok()?;
+    query.remove(param).filter(|token| !token.is_empty())
+}
+
+fn protocol_token(parts: &Parts) -> Option<String> {
+    parts
+        .headers
+        .get_all(header::SEC_WEBSOCKET_PROTOCOL)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .find_map(|protocol| protocol.trim().strip_prefix(BEARER_PROTOCOL_PREFIX))
+        .filter(|token| !token.is_empty())
+        .map(str::to_string)
+}
+
+#[derive(Deserialize)]
+struct AuthMessage {
+    token: String,
+}
+
+async fn first_message_user<T>(
+    socket: &mut WebSocket,
+    verifier: &T,
+    cfg: &WsCfg,
+) -> Option<AuthUser>
+where
+    T: AccessTokenVerifier,
+{
+    let wait = Duration::from_secs(cfg.auth_timeout_secs);
+    let message = timeout(wait, socket.recv()).await.ok()??.ok()?;
+    let Message::Text(text) = message else {
+        return None;
+    };
+    let AuthMessage { token } = serde_json::from_str(text.as_str()).ok()?;
+    verify_token(verifier, &token).ok()
+}
+
+fn close(code: u16, reason: &str) -> Message {
+    Message::Close(Some(CloseFrame {
+        code,
+        reason: reason.into(),
+    }))
+}
+
+/// A WebSocket exchanging JSON messages: `In` from the client, `Out` to it.
+///
+/// [`JsonSocket::recv`] sends pings every `ping_interval_secs` and closes the socket after
+/// `idle_timeout_secs` without traffic, so it should be polled continuously.
+pub struct JsonSocket<In, Out> {
+    socket: WebSocket,
+    ping_interval: Duration,
+    idle_timeout: Duration,
+    _codec: PhantomData<fn(Out) -> In>,
+}
+
+impl<In, Out> JsonSocket<In, Out>
+where
+    In: DeserializeOwned,
+    Out: Serialize,
+{
+    fn new(socket: WebSocket, cfg: &WsCfg) -> Self {
+        JsonSocket {
+            socket,
+            ping_interval: Duration::from_secs(cfg.ping_interval_secs.max(1)),
+            idle_timeout: Duration::from_secs(cfg.idle_timeout_secs.max(1)),
+            _codec: PhantomData,
+        }
+    }
+
+    /// Next message from the client.
+    ///
+    /// Returns `Some(Err(_))` for a message that is not a valid `In`, leaving the socket open,
+    /// and `None` once the socket is closed, broken or idle.
+    pub async fn recv(&mut self) -> Option<Result<In>> {
+        let start = Instant::now();
+        let mut ping = interval_at(start + self.ping_interval, self.ping_interval);
+        ping.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut idle_deadline = start + self.idle_timeout;
+        loop {
+            tokio::select! {
+                message = self.socket.recv() => {
+                    idle_deadline = Instant::now() + self.idle_timeout;
+                    match message? {
+                        Ok(Message::Text(text)) => return Some(decode(text.as_bytes())),
+                        Ok(Message::Binary(bytes)) => return Some(decode(&bytes)),
+                        Ok(Message::Ping(_) | Message::Pong(_)) => {}
+                        Ok(Message::Close(_)) | Err(_) => return None,
+                    }
+                }
+                _ = ping.tick() => {
+                    if self.socket.send(Message::Ping(Default::default())).await.is_err() {
+                        return None;
+                    }
+                }
+                _ = sleep_until(idle_deadline) => {
+                    let _ = self.socket.send(close(close_code::AWAY, "idle timeout")).await;
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// Send `message` as a JSON text frame.
+    pub async fn send(&mut self, message: &Out) -> Result<()> {
+        let text = serde_json::to_string(message)
+            .map_err(|e| Error::ErrorMessage(format!("encode websocket message: {e}").into()))?;
+        self.socket
+            .send(Message::Text(text.into()))
+            .await
+            .map_err(|e| Error::ErrorMessage(format!("send websocket message: {e}").into()))
+    }
+
+    /// Close the socket with `code` and `reason`.
+    pub async fn close(mut self, code: u16, reason: &str) {
+        let _ = self.socket.send(close(code, reason)).await;
+    }
+
+    /// The underlying socket, for raw frames.
+    pub fn into_inner(self) -> WebSocket {
+        self.socket
+    }
+}
+
+fn decode<In>(bytes: &[u8]) -> Result<In>
+where
+    In: DeserializeOwned,
+{
+    serde_json::from_slice(bytes)
+        .map_err(|e| Error::ErrorMessage(format!("decode websocket message: {e}").into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use axum::{Extension, Router, routing::get};
+    use futures_util::{SinkExt, StreamExt};
+    use serde_json::{Value, json};
+    use tokio_tungstenite::{
+        connect_async,
+        tungstenite::{self, client::IntoClientRequest},
+    };
+    use toolcraft_jwt::Claims;
+
+    use super::*;
+
+    struct StaticVerifier;
+
+    impl AccessTokenVerifier for StaticVerifier {
+        fn validate_access_token(&self, token: &str) -> toolcraft_jwt::Result<Claims> {
+            match token {
+                "good" => Ok(Claims {
+                    iss: String::new(),
+                    aud: String::new(),
+                    sub: String::from("user-1"),
+                    exp: 0,
+                    iat: 0,
+                    ext: None,
+                }),
+                _ => Err(toolcraft_jwt::error::Error::AuthError("bad token".into())),
+            }
+        }
+    }
+
+    async fn echo(ws: AuthWsUpgrade<StaticVerifier>) -> Response {
+        ws.on_upgrade(|user, mut socket: JsonSocket<Value, Value>| async move {
+            while let Some(message) = socket.recv().await {
+                let reply = match message {
+                    Ok(message) => json!({ "user": user.user_id, "echo": message }),
+                    Err(_) => json!({ "error": "invalid message" }),
+                };
+                if socket.send(&reply).await.is_err() {
+                    break;
+                }
+            }
+        })
+    }
+
+    async fn serve(cfg: WsCfg) -> SocketAddr {
+        let app = Router::new()
+            .route("/ws", get(echo))
+            .layer(Extension(Arc::new(StaticVerifier)))
+            .layer(Extension(cfg));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        addr
+    }
+
+    fn text(message: tungstenite::Message) -> Value {
+        serde_json::from_str(message.to_text().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_query_token() {
+        let addr = serve(WsCfg::default()).await;
+        let err = connect_async(format!("ws://{addr}/ws?access_token=bad"))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, tungstenite::Error::Http(response) if response.status() == 401),
+            "{err}"
+        );
+
+        let (mut socket, _) = connect_async(format!("ws://{addr}/ws?access_token=good"))
+            .await
+            .unwrap();
+        socket
+            .send(tungstenite::Message::text(r#"{"hello":1}"#))
+            .await
+            .unwrap();
+        let reply = text(socket.next().await.unwrap().unwrap());
+        assert_eq!(reply, json!({"user": "user-1", "echo": {"hello": 1}}));
+
+        socket
+            .send(tungstenite::Message::text("not json"))
+            .await
+            .unwrap();
+        let reply = text(socket.next().await.unwrap().unwrap());
+        assert_eq!(reply["error"], "invalid message");
+    }
+
+    #[tokio::test]
+    async fn test_subprotocol_token() {
+        let addr = serve(WsCfg {
+            token_source: WsTokenSource::Subprotocol,
+            protocols: vec![String::from("json")],
+            ..WsCfg::default()
+        })
+        .await;
+        let mut request = format!("ws://{addr}/ws").into_client_request().unwrap();
+        request.headers_mut().insert(
+            header::SEC_WEBSOCKET_PROTOCOL,
+            "json, bearer.good".parse().unwrap(),
+        );
+        let (_, response) = connect_async(request).await.unwrap();
+        assert_eq!(response.headers()[header::SEC_WEBSOCKET_PROTOCOL], "json");
+    }
+
+    #[tokio::test]
+    async fn test_first_message_token_and_idle_timeout() {
+        let addr = serve(WsCfg {
+            token_source: WsTokenSource::FirstMessage,
+            idle_timeout_secs: 1,
+            ..WsCfg::default()
+        })
+        .await;
+
+        let (mut socket, _) = connect_async(format!("ws://{addr}/ws")).await.unwrap();
+        socket
+            .send(tungstenite::Message::text(r#"{"token":"bad"}"#))
+            .await
+            .unwrap();
+        let message = socket.next().await.unwrap().unwrap();
+        assert!(
+            matches!(message, tungstenite::Message::Close(Some(ref frame)) if frame.code == close_code::POLICY.into()),
+            "{message:?}"
+        );
+
+        let (mut socket, _) = connect_async(format!("ws://{addr}/ws")).await.unwrap();
+        socket
+            .send(tungstenite::Message::text(r#"{"token":"good"}"#))
+            .await
+            .unwrap();
+        socket.send(tungstenite::Message::text("42")).await.unwrap();
+        let reply = text(socket.next().await.unwrap().unwrap());
+        assert_eq!(reply["echo"], 42);
+
+        // Nothing sent for longer than the idle timeout: the server closes the socket.
+        let message = socket.next().await.unwrap().unwrap();
+        assert!(
+            matches!(message, tungstenite::Message::Close(Some(ref frame)) if frame.code == close_code::AWAY.into()),
+            "{message:?}"
+        );
+    }
+}
@@ -80,6 +80,16 @@ enum TokenKind {
     Refesh,
 }
 
+#[cfg(feature = "metrics")]
+impl TokenKind {
+    fn label(&self) -> &'static str {
+        match self {
+            TokenKind::Access => "access",
+            TokenKind::Refesh => "refresh",
+        }
+    }
+}
+
 /// Struct representing the JWT configuration and operations.
 #[derive(Clone)]
 pub struct Jwt {
@@ -123,6 +133,8 @@ impl Jwt {
         validation_access_key.validate_exp = cfg.access_key_validate_exp;
         validation_refresh_key.validate_exp = cfg.refresh_key_validate_exp;
         validation_refresh_key.required_spec_claims.clear();
+        #[cfg(feature = "metrics")]
+        crate::metrics::register();
         Ok(Self {
             header,
             encoding_access_key,
@@ -181,7 +193,11 @@ impl Jwt {
 
     fn validate_token(&self, kind: &TokenKind, token: &str) -> Result<TokenData<Claims>> {
         let (key, validation) = self.select_decoding_key_and_validation(kind);
-        decode::<Claims>(token, key, validation).map_err(|e| Error::AuthError(e.to_string().into()))
+        decode::<Claims>(token, key, validation).map_err(|e| {
+            #[cfg(feature = "metrics")]
+            crate::metrics::record_validation_failure(kind.label(), &e);
+            Error::AuthError(e.to_string().into())
+        })
     }
 
     fn get_token_duration(&self, kind: &TokenKind) -> usize {
@@ -402,6 +418,29 @@ MCowBQYDK2VwAyEA2+Jj2UvNCvQiUPNYRgSi0cJSPiJI6Rs6D0UTeEpQVj8=
         assert_eq!(claims.sub, "test_sub");
     }
 
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_validation_failure_is_counted() {
+        let jwt = setup_jwt();
+        assert!(jwt.validate_access_token("not-a-token").is_err());
+
+        let families = prometheus::default_registry().gather();
+        let family = families
+            .iter()
+            .find(|f| f.name() == "toolcraft_jwt_validation_failures_total")
+            .expect("counter is registered");
+        let malformed = family
+            .get_metric()
+            .iter()
+            .find(|m| {
+                m.get_label()
+                    .iter()
+                    .any(|l| l.name() == "reason" && l.value() == "malformed")
+            })
+            .expect("malformed failures are labelled");
+        assert!(malformed.get_counter().get_value() >= 1.0);
+    }
+
     #[test]
     fn test_refresh_access_token_keeps_ext() {
         let jwt = setup_jwt();
@@ -1,5 +1,7 @@
 pub mod error;
 mod jwt;
+#[cfg(feature = "metrics")]
+mod metrics;
 mod verify;
 
 pub use jwt::{Claims, Jwt, JwtCfg, TokenPair};
@@ -0,0 +1,37 @@
+use std::sync::LazyLock;
+
+use jsonwebtoken::errors::{Error, ErrorKind};
+use prometheus::{IntCounterVec, register_int_counter_vec};
+
+/// Rejected tokens, registered in `prometheus::default_registry()`. `None` if the registry
+/// refused the counter, for example because the application registered the same name.
+static VALIDATION_FAILURES: LazyLock<Option<IntCounterVec>> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "toolcraft_jwt_validation_failures_total",
+        "JWT validations rejected, by token kind and reason",
+        &["token", "reason"]
+    )
+    .inspect_err(|e| tracing::warn!(error = %e, "JWT metrics are disabled"))
+    .ok()
+});
+
+/// Register the counter up front so it is exported before the first failure.
+pub(crate) fn register() {
+    LazyLock::force(&VALIDATION_FAILURES);
+}
+
+pub(crate) fn record_validation_failure(token: &str, error: &Error) {
+    let reason = match error.kind() {
+        ErrorKind::ExpiredSignature => "expired",
+        ErrorKind::ImmatureSignature => "not_yet_valid",
+        ErrorKind::InvalidSignature => "invalid_signature",
+        ErrorKind::InvalidIssuer
+        | ErrorKind::InvalidAudience
+        | ErrorKind::InvalidSubject
+        | ErrorKind::MissingRequiredClaim(_) => "invalid_claims",
+        _ => "malformed",
+    };
+    if let Some(counter) = VALIDATION_FAILURES.as_ref() {
+        counter.with_label_values(&[token, reason]).inc();
+    }
+}
@@ -38,6 +38,8 @@ impl VerifyJwt {
         validation.set_issuer(&[issuer]);
         validation.set_audience(&[audience]);
         validation.validate_aud = true;
+        #[cfg(feature = "metrics")]
+        crate::metrics::register();
         Ok(Self {
             decoding_key,
             validation,
@@ -48,7 +50,11 @@ impl VerifyJwt {
     pub fn validate_token(&self, token: &str) -> Result<Claims> {
         decode::<Claims>(token, &self.decoding_key, &self.validation)
             .map(|data| data.claims)
-            .map_err(|e| Error::AuthError(e.to_string().into()))
+            .map_err(|e| {
+                #[cfg(feature = "metrics")]
+                crate::metrics::record_validation_failure("access", &e);
+                Error::AuthError(e.to_string().into())
+            })
     }
 }
 
@@ -0,0 +1,315 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use reqwest::{Method, header, multipart};
+use serde::Serialize;
+
+use crate::{
+    client::{FormField, Request},
+    error::{Error, Result},
+    header_map::HeaderMap,
+    response::Response,
+};
+
+/// A single request being put together, created by [`Request::request`].
+///
+/// Errors from the chained calls, such as an invalid header value, are reported by
+/// [`RequestBuilder::send`].
+///
+/// # Example
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use serde_json::json;
+/// use toolcraft_request::{Method, Request};
+///
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let mut client = Request::new()?;
+/// client.set_base_url("https://api.example.com")?;
+/// let response = client
+///     .request(Method::PATCH, "/users/42")
+///     .query([("notify", "false")])
+///     .bearer("token123")
+///     .json(&json!({"name": "John"}))
+///     .timeout(Duration::from_secs(5))
+///     .send()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct RequestBuilder<'a> {
+    client: &'a Request,
+    method: Method,
+    endpoint: String,
+    query: Vec<(String, String)>,
+    headers: HeaderMap,
+    body: Body,
+    timeout: Option<Duration>,
+    error_for_status: Option<bool>,
+    error: Option<Error>,
+}
+
+#[derive(Debug)]
+enum Body {
+    Empty,
+    Bytes(Bytes),
+    Json(Bytes),
+    Form(Vec<FormField>),
+}
+
+impl<'a> RequestBuilder<'a> {
+    pub(crate) fn new(client: &'a Request, method: Method, endpoint: &str) -> Self {
+        RequestBuilder {
+            client,
+            method,
+            endpoint: endpoint.to_string(),
+            query: Vec::new(),
+            headers: HeaderMap::new(),
+            body: Body::Empty,
+            timeout: None,
+            error_for_status: None,
+            error: None,
+        }
+    }
+
+    /// Append query parameters.
+    pub fn query<I, K, V>(mut self, params: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.query
+            .extend(params.into_iter().map(|(k, v)| (k.into(), v.into())));
+        self
+    }
+
+    /// Set a header, overriding the client's default headers.
+    pub fn header(mut self, name: impl AsRef<str>, value: impl Into<String>) -> Self {
+        if let Err(e) = self.headers.insert(name, value.into()) {
+            self.error.get_or_insert(e);
+        }
+        self
+    }
+
+    /// Set several headers, overriding the client's default headers.
+    pub fn headers(mut self, headers: HeaderMap) -> Self {
+        self.headers.merge(headers);
+        self
+    }
+
+    /// Set `Authorization: Bearer <token>`.
+    pub fn bearer(self, token: impl AsRef<str>) -> Self {
+        let value = format!("Bearer {}", token.as_ref());
+        self.header(header::AUTHORIZATION, value)
+    }
+
+    /// Send `body` as JSON, with `Content-Type: application/json` unless the request or the
+    /// client's default headers set another one, such as `application/merge-patch+json`.
+    pub fn json<T: Serialize + ?Sized>(mut self, body: &T) -> Self {
+        match serde_json::to_vec(body) {
+            Ok(bytes) => self.body = Body::Json(bytes.into()),
+            Err(e) => {
+                self.error
+                    .get_or_insert(Error::ErrorMessage(e.to_string().into()));
+            }
+        }
+        self
+    }
+
+    /// Send `fields` as `multipart/form-data`. Any `Content-Type` header is dropped so the
+    /// boundary set by reqwest is kept.
+    pub fn form(mut self, fields: Vec<FormField>) -> Self {
+        self.body = Body::Form(fields);
+        self
+    }
+
+    /// Send a raw body.
+    pub fn body(mut self, body: impl Into<Bytes>) -> Self {
+        self.body = Body::Bytes(body.into());
+        self
+    }
+
+    /// Time limit for this request, overriding the client's timeout.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Fail with [`Error::HttpError`] on a non-2xx status, overriding
+    /// [`Request::set_error_for_status`].
+    pub fn error_for_status(mut self, enabled: bool) -> Self {
+        self.error_for_status = Some(enabled);
+        self
+    }
+
+    /// Send the request, applying the client's base URL, default headers and retry policy.
+    pub async fn send(self) -> Result<Response> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        let query = (!self.query.is_empty()).then_some(self.query);
+        let url = self.client.build_url(&self.endpoint, query)?;
+
+        let mut headers = self.client.default_headers().clone();
+        headers.merge(self.headers);
+
+        let mut request = self.client.inner().request(self.method, url);
+        request = match self.body {
+            Body::Empty => request,
+            Body::Bytes(bytes) => request.body(bytes),
+            Body::Json(bytes) => {
+                if !headers.contains(header::CONTENT_TYPE) {
+                    headers.inner_mut().insert(
+                        header::CONTENT_TYPE,
+                        header::HeaderValue::from_static("application/json"),
+                    );
+                }
+                request.body(bytes)
+            }
+            Body::Form(fields) => {
+                headers.remove(header::CONTENT_TYPE);
+                request.multipart(multipart_form(fields))
+            }
+        };
+        request = request.headers(headers.inner().clone());
+        if let Some(timeout) = self.timeout {
+            request = request.timeout(timeout);
+        }
+
+        let response = self.client.execute(request).await?;
+        if self
+            .error_for_status
+            .unwrap_or_else(|| self.client.errors_for_status())
+        {
+            return response.error_for_status().await;
+        }
+        Ok(response)
+    }
+}
+
+fn multipart_form(fields: Vec<FormField>) -> multipart::Form {
+    fields
+        .into_iter()
+        .fold(multipart::Form::new(), |form, field| match field {
+            FormField::Text { name, value } => form.text(name, value),
+            FormField::File {
+                name,
+                filename,
+                content,
+            } => form.part(name, multipart::Part::bytes(content).file_name(filename)),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::stub;
+
+    #[tokio::test]
+    async fn test_builder_sends_method_query_headers_and_body() {
+        let mut client = Request::new().unwrap();
+        let (url, _) = stub::serve(|_, request| stub::response("200 OK", &[], request)).await;
+        client.set_base_url(&url).unwrap();
+        let mut defaults = HeaderMap::new();
+        defaults
+            .insert("x-client", String::from("default"))
+            .unwrap();
+        defaults.insert("x-trace", String::from("default")).unwrap();
+        client.set_default_headers(defaults);
+
+        let echoed = client
+            .request(Method::PATCH, "users/42")
+            .query([("notify", "false")])
+            .header("x-trace", "override")
+            .bearer("secret")
+            .json(&json!({"name": "John"}))
+            .timeout(Duration::from_secs(5))
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap()
+            .to_ascii_lowercase();
+
+        assert!(
+            echoed.starts_with("patch /users/42?notify=false http/1.1"),
+            "{echoed}"
+        );
+        for line in [
+            "x-client: default",
+            "x-trace: override",
+            "authorization: bearer secret",
+            "content-type: application/json",
+        ] {
+            assert!(echoed.contains(line), "{echoed}");
+        }
+        assert!(echoed.ends_with(r#"{"name":"john"}"#), "{echoed}");
+    }
+
+    #[tokio::test]
+    async fn test_json_keeps_caller_content_type() {
+        let mut client = Request::new().unwrap();
+        let (url, _) = stub::serve(|_, request| stub::response("200 OK", &[], request)).await;
+        client.set_base_url(&url).unwrap();
+        let body = json!({"name": "John"});
+
+        let mut headers = HeaderMap::new();
+        headers
+            .insert("content-type", String::from("application/merge-patch+json"))
+            .unwrap();
+        let echoed = client
+            .put("users/42", &body, Some(headers))
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap()
+            .to_ascii_lowercase();
+        assert!(
+            echoed.contains("content-type: application/merge-patch+json"),
+            "{echoed}"
+        );
+        assert!(
+            !echoed.contains("content-type: application/json"),
+            "{echoed}"
+        );
+
+        let mut defaults = HeaderMap::new();
+        defaults
+            .insert("content-type", String::from("application/vnd.api+json"))
+            .unwrap();
+        client.set_default_headers(defaults);
+        let echoed = client
+            .post("users", &body, None)
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap()
+            .to_ascii_lowercase();
+        assert!(
+            echoed.contains("content-type: application/vnd.api+json"),
+            "{echoed}"
+        );
+        assert!(
+            !echoed.contains("content-type: application/json"),
+            "{echoed}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_header_is_reported_on_send() {
+        let client = Request::new().unwrap();
+        let result = client
+            .request(Method::GET, "http://127.0.0.1:1/")
+            .header("x-bad", "line\nbreak")
+            .send()
+            .await;
+        assert!(result.is_err());
+    }
+}
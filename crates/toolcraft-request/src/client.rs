@@ -1,19 +1,24 @@
-use futures_util::StreamExt;
-use reqwest::{Client, multipart};
+use reqwest::{Client, Method};
+use serde::{Serialize, de::DeserializeOwned};
 use url::Url;
 
 use crate::{
+    builder::RequestBuilder,
     error::{Error, Result},
     header_map::HeaderMap,
     response::{ByteStream, Response},
+    retry::RetryPolicy,
+    sse::{self, SseReconnect, SseRequest, SseStream},
 };
 
 /// An HTTP request builder and executor with base URL and default headers.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Request {
     client: Client,
     base_url: Option<Url>,
     default_headers: HeaderMap,
+    retry_policy: Option<RetryPolicy>,
+    error_for_status: bool,
 }
 
 impl Request {
@@ -26,6 +31,8 @@ impl Request {
             client,
             base_url: None,
             default_headers: HeaderMap::new(),
+            retry_policy: None,
+            error_for_status: false,
         })
     }
 
@@ -38,6 +45,8 @@ impl Request {
             client,
             base_url: None,
             default_headers: HeaderMap::new(),
+            retry_policy: None,
+            error_for_status: false,
         })
     }
 
@@ -57,6 +66,22 @@ impl Request {
         self.default_headers = headers;
     }
 
+    /// Retry failed requests according to `policy`. Requests are sent once by default.
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry_policy = Some(policy);
+    }
+
+    /// Fail every request answered with a non-2xx status with [`Error::HttpError`]. Off by
+    /// default, leaving the status for the caller to check.
+    pub fn set_error_for_status(&mut self, enabled: bool) {
+        self.error_for_status = enabled;
+    }
+
+    /// Start a request with any method; see [`RequestBuilder`].
+    pub fn request(&self, method: Method, endpoint: &str) -> RequestBuilder<'_> {
+        RequestBuilder::new(self, method, endpoint)
+    }
+
     /// Send a GET request.
     pub async fn get(
         &self,
@@ -64,17 +89,50 @@ impl Request {
         query: Option<Vec<(String, String)>>,
         headers: Option<HeaderMap>,
     ) -> Result<Response> {
-        let url = self.build_url(endpoint, query)?;
-        let mut request = self.client.get(url.as_str());
+        self.verb(Method::GET, endpoint, headers)
+            .query(query.unwrap_or_default())
+            .send()
+            .await
+    }
 
-        let mut combined_headers = self.default_headers.clone();
-        if let Some(custom_headers) = headers {
-            combined_headers.merge(custom_headers);
-        }
-        request = request.headers(combined_headers.inner().clone());
+    /// Send a GET request and decode the JSON response, failing on non-2xx statuses.
+    pub async fn get_json<T>(
+        &self,
+        endpoint: &str,
+        query: Option<Vec<(String, String)>>,
+        headers: Option<HeaderMap>,
+    ) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        self.verb(Method::GET, endpoint, headers)
+            .query(query.unwrap_or_default())
+            .error_for_status(true)
+            .send()
+            .await?
+            .json()
+            .await
+    }
 
-        let response = request.send().await?;
-        Ok(response.into())
+    /// Send `body` as JSON in a POST request and decode the JSON response, failing on non-2xx
+    /// statuses.
+    pub async fn post_json<B, T>(
+        &self,
+        endpoint: &str,
+        body: &B,
+        headers: Option<HeaderMap>,
+    ) -> Result<T>
+    where
+        B: Serialize + ?Sized,
+        T: DeserializeOwned,
+    {
+        self.verb(Method::POST, endpoint, headers)
+            .json(body)
+            .error_for_status(true)
+            .send()
+            .await?
+            .json()
+            .await
     }
 
     /// Send a POST request with JSON body.
@@ -84,17 +142,10 @@ impl Request {
         body: &serde_json::Value,
         headers: Option<HeaderMap>,
     ) -> Result<Response> {
-        let url = self.build_url(endpoint, None)?;
-        let mut request = self.client.post(url).json(body);
-
-        let mut combined_headers = self.default_headers.clone();
-        if let Some(custom_headers) = headers {
-            combined_headers.merge(custom_headers);
-        }
-        request = request.headers(combined_headers.inner().clone());
-
-        let response = request.send().await?;
-        Ok(response.into())
+        self.verb(Method::POST, endpoint, headers)
+            .json(body)
+            .send()
+            .await
     }
 
     /// Send a PUT request with JSON body.
@@ -104,17 +155,23 @@ impl Request {
         body: &serde_json::Value,
         headers: Option<HeaderMap>,
     ) -> Result<Response> {
-        let url = self.build_url(endpoint, None)?;
-        let mut request = self.client.put(url).json(body);
-
-        let mut combined_headers = self.default_headers.clone();
-        if let Some(custom_headers) = headers {
-            combined_headers.merge(custom_headers);
-        }
-        request = request.headers(combined_headers.inner().clone());
+        self.verb(Method::PUT, endpoint, headers)
+            .json(body)
+            .send()
+            .await
+    }
 
-        let response = request.send().await?;
-        Ok(response.into())
+    /// Send a PATCH request with JSON body.
+    pub async fn patch(
+        &self,
+        endpoint: &str,
+        body: &serde_json::Value,
+        headers: Option<HeaderMap>,
+    ) -> Result<Response> {
+        self.verb(Method::PATCH, endpoint, headers)
+            .json(body)
+            .send()
+            .await
     }
 
     /// Send a PUT request with raw bytes body.
@@ -124,47 +181,33 @@ impl Request {
         body: impl Into<bytes::Bytes>,
         headers: Option<HeaderMap>,
     ) -> Result<Response> {
-        let url = self.build_url(endpoint, None)?;
-        let mut request = self.client.put(url).body(body.into());
-
-        let mut combined_headers = self.default_headers.clone();
-        if let Some(custom_headers) = headers {
-            combined_headers.merge(custom_headers);
-        }
-        request = request.headers(combined_headers.inner().clone());
+        self.verb(Method::PUT, endpoint, headers)
+            .body(body)
+            .send()
+            .await
+    }
 
-        let response = request.send().await?;
-        Ok(response.into())
+    /// Send a POST request with raw bytes body.
+    pub async fn post_bytes(
+        &self,
+        endpoint: &str,
+        body: impl Into<bytes::Bytes>,
+        headers: Option<HeaderMap>,
+    ) -> Result<Response> {
+        self.verb(Method::POST, endpoint, headers)
+            .body(body)
+            .send()
+            .await
     }
 
     /// Send a DELETE request.
     pub async fn delete(&self, endpoint: &str, headers: Option<HeaderMap>) -> Result<Response> {
-        let url = self.build_url(endpoint, None)?;
-        let mut request = self.client.delete(url);
-
-        let mut combined_headers = self.default_headers.clone();
-        if let Some(custom_headers) = headers {
-            combined_headers.merge(custom_headers);
-        }
-        request = request.headers(combined_headers.inner().clone());
-
-        let response = request.send().await?;
-        Ok(response.into())
+        self.verb(Method::DELETE, endpoint, headers).send().await
     }
 
     /// Send a HEAD request.
     pub async fn head(&self, endpoint: &str, headers: Option<HeaderMap>) -> Result<Response> {
-        let url = self.build_url(endpoint, None)?;
-        let mut request = self.client.head(url);
-
-        let mut combined_headers = self.default_headers.clone();
-        if let Some(custom_headers) = headers {
-            combined_headers.merge(custom_headers);
-        }
-        request = request.headers(combined_headers.inner().clone());
-
-        let response = request.send().await?;
-        Ok(response.into())
+        self.verb(Method::HEAD, endpoint, headers).send().await
     }
 
     /// Send a POST request with multipart/form-data.
@@ -198,72 +241,149 @@ impl Request {
         form_fields: Vec<FormField>,
         headers: Option<HeaderMap>,
     ) -> Result<Response> {
-        let url = self.build_url(endpoint, None)?;
-
-        let mut form = multipart::Form::new();
-        for field in form_fields {
-            match field {
-                FormField::Text { name, value } => {
-                    form = form.text(name, value);
-                }
-                FormField::File {
-                    name,
-                    filename,
-                    content,
-                } => {
-                    let part = multipart::Part::bytes(content).file_name(filename);
-                    form = form.part(name, part);
-                }
-            }
-        }
-
-        let mut combined_headers = self.default_headers.clone();
-        if let Some(custom_headers) = headers {
-            combined_headers.merge(custom_headers);
-        }
-
-        // Remove Content-Type to let reqwest set the correct multipart/form-data with boundary
-        combined_headers.remove("Content-Type");
-        combined_headers.remove("content-type");
-
-        let mut request = self.client.post(url).multipart(form);
-        request = request.headers(combined_headers.inner().clone());
-
-        let response = request.send().await?;
-        Ok(response.into())
+        self.verb(Method::POST, endpoint, headers)
+            .form(form_fields)
+            .send()
+            .await
     }
 
-    /// Send a streaming POST request and return the response stream.
+    /// Send a streaming POST request and return the response stream, failing on non-2xx
+    /// statuses.
     pub async fn post_stream(
         &self,
         endpoint: &str,
         body: &serde_json::Value,
         headers: Option<HeaderMap>,
     ) -> Result<ByteStream> {
-        let url = self.build_url(endpoint, None)?;
-        let mut request = self.client.post(url).json(body);
+        let response = self
+            .verb(Method::POST, endpoint, headers)
+            .json(body)
+            .error_for_status(true)
+            .send()
+            .await?;
+        Ok(response.bytes_stream())
+    }
 
-        let mut combined_headers = self.default_headers.clone();
-        if let Some(custom_headers) = headers {
-            combined_headers.merge(custom_headers);
-        }
-        request = request.headers(combined_headers.inner().clone());
+    /// Open a Server-Sent Events stream with a GET request, failing on non-2xx statuses.
+    ///
+    /// With `reconnect`, a dropped stream is reopened with `Last-Event-ID` set to the last event
+    /// id received.
+    pub async fn get_sse(
+        &self,
+        endpoint: &str,
+        query: Option<Vec<(String, String)>>,
+        headers: Option<HeaderMap>,
+        reconnect: Option<SseReconnect>,
+    ) -> Result<SseStream> {
+        let request = SseRequest {
+            method: Method::GET,
+            endpoint: endpoint.to_string(),
+            query: query.unwrap_or_default(),
+            body: None,
+            headers,
+        };
+        sse::connect(self, request, reconnect).await
+    }
 
-        let response = request.send().await?;
-        if !response.status().is_success() {
-            return Err(Error::ErrorMessage(
-                format!("Unexpected status: {}", response.status()).into(),
-            ));
+    /// Open a Server-Sent Events stream with a JSON POST request, as used by LLM chat APIs. See
+    /// [`Request::get_sse`] for `reconnect`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use futures_util::StreamExt;
+    /// use serde_json::json;
+    /// use toolcraft_request::Request;
+    ///
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let client = Request::new()?;
+    /// let body = json!({"model": "gpt-4", "stream": true});
+    /// let mut events = client
+    ///     .post_sse("https://api.example.com/chat", &body, None, None)
+    ///     .await?;
+    /// while let Some(event) = events.next().await {
+    ///     println!("{}", event?.data);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn post_sse(
+        &self,
+        endpoint: &str,
+        body: &serde_json::Value,
+        headers: Option<HeaderMap>,
+        reconnect: Option<SseReconnect>,
+    ) -> Result<SseStream> {
+        let request = SseRequest {
+            method: Method::POST,
+            endpoint: endpoint.to_string(),
+            query: Vec::new(),
+            body: Some(body.clone()),
+            headers,
+        };
+        sse::connect(self, request, reconnect).await
+    }
+
+    fn verb(
+        &self,
+        method: Method,
+        endpoint: &str,
+        headers: Option<HeaderMap>,
+    ) -> RequestBuilder<'_> {
+        let builder = self.request(method, endpoint);
+        match headers {
+            Some(headers) => builder.headers(headers),
+            None => builder,
         }
+    }
+
+    pub(crate) fn inner(&self) -> &Client {
+        &self.client
+    }
 
-        let stream = response
-            .bytes_stream()
-            .map(|chunk_result| chunk_result.map_err(Error::from));
-        Ok(Box::pin(stream))
+    pub(crate) fn default_headers(&self) -> &HeaderMap {
+        &self.default_headers
+    }
+
+    pub(crate) fn errors_for_status(&self) -> bool {
+        self.error_for_status
+    }
+
+    /// Send a request, retrying it as the retry policy allows.
+    pub(crate) async fn execute(&self, request: reqwest::RequestBuilder) -> Result<Response> {
+        let request = request.build()?;
+        let policy = match &self.retry_policy {
+            Some(policy) if policy.allows(request.method()) => policy,
+            _ => return Ok(self.client.execute(request).await?.into()),
+        };
+
+        let mut attempt = 1;
+        loop {
+            // Bodies that cannot be replayed, such as multipart streams, get a single attempt.
+            let retry = match request.try_clone() {
+                Some(retry) if attempt < policy.max_attempts() => retry,
+                _ => return Ok(self.client.execute(request).await?.into()),
+            };
+            let delay = match self.client.execute(retry).await {
+                Ok(response) => {
+                    match policy.delay_for_response(response.status(), response.headers(), attempt)
+                    {
+                        Some(delay) => delay,
+                        None => return Ok(response.into()),
+                    }
+                }
+                Err(e) => policy.delay_for_error(&e, attempt).ok_or(e)?,
+            };
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
     }
 
     /// Build a full URL by combining base URL, endpoint, and optional query parameters.
-    fn build_url(&self, endpoint: &str, query: Option<Vec<(String, String)>>) -> Result<Url> {
+    pub(crate) fn build_url(
+        &self,
+        endpoint: &str,
+        query: Option<Vec<(String, String)>>,
+    ) -> Result<Url> {
         let mut url = if let Some(base_url) = &self.base_url {
             base_url.join(endpoint)?
         } else {
@@ -367,3 +487,132 @@ impl FormField {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use reqwest::StatusCode;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{error::MAX_ERROR_BODY, stub};
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct User {
+        id: u32,
+        name: String,
+    }
+
+    async fn client(status: &'static str, body: &'static [u8]) -> Request {
+        let (url, _) = stub::serve(move |_, _| {
+            stub::response(status, &[("content-type", "application/json")], body)
+        })
+        .await;
+        let mut client = Request::new().unwrap();
+        client.set_base_url(&url).unwrap();
+        client
+    }
+
+    #[tokio::test]
+    async fn test_typed_json_helpers() {
+        let client = client("200 OK", br#"{"id":1,"name":"John"}"#).await;
+        let user: User = client.get_json("users/1", None, None).await.unwrap();
+        assert_eq!(
+            user,
+            User {
+                id: 1,
+                name: String::from("John")
+            }
+        );
+        let user: User = client
+            .post_json("users", &json!({"name": "John"}), None)
+            .await
+            .unwrap();
+        assert_eq!(user.id, 1);
+    }
+
+    #[tokio::test]
+    async fn test_non_2xx_maps_to_http_error() {
+        let client = client(
+            "404 Not Found",
+            br#"{"code":404,"message":"user not found","request_id":"req-1"}"#,
+        )
+        .await;
+        let err = client
+            .get_json::<User>("users/2", None, None)
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), Some(StatusCode::NOT_FOUND));
+        let Error::HttpError(http) = err else {
+            panic!("unexpected error: {err}");
+        };
+        assert_eq!(http.headers["content-type"], "application/json");
+        let common = http.common_error().unwrap();
+        assert_eq!(
+            (common.code, common.message.as_str()),
+            (404, "user not found")
+        );
+        assert_eq!(common.request_id.as_deref(), Some("req-1"));
+
+        // Plain calls keep the response unless the mode is on.
+        let response = client.get("users/2", None, None).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let mut client = client;
+        client.set_error_for_status(true);
+        assert!(client.get("users/2", None, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_http_error_body_is_truncated() {
+        static BODY: [u8; MAX_ERROR_BODY * 2] = [b'x'; MAX_ERROR_BODY * 2];
+        let client = client("500 Internal Server Error", &BODY).await;
+        let Err(Error::HttpError(http)) = client
+            .request(Method::GET, "boom")
+            .error_for_status(true)
+            .send()
+            .await
+        else {
+            panic!("expected an http error");
+        };
+        assert!(http.truncated);
+        assert_eq!(http.body.len(), MAX_ERROR_BODY);
+        assert!(http.common_error().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_http_error_body_is_cut_at_a_char_boundary() {
+        // `\u{FFFD}` is 3 bytes, so the limit falls inside one of them.
+        let body = "\u{FFFD}".repeat(MAX_ERROR_BODY);
+        let client = client("502 Bad Gateway", body.leak().as_bytes()).await;
+        let Err(Error::HttpError(http)) = client
+            .request(Method::GET, "boom")
+            .error_for_status(true)
+            .send()
+            .await
+        else {
+            panic!("expected an http error");
+        };
+        assert!(http.truncated);
+        assert_eq!(http.body, "\u{FFFD}".repeat(MAX_ERROR_BODY / 3));
+    }
+
+    #[tokio::test]
+    async fn test_http_error_keeps_status_when_body_fails() {
+        let (url, _) = stub::serve(|_, _| {
+            b"HTTP/1.1 503 Service Unavailable\r\ncontent-length: 100\r\n\r\npartial".to_vec()
+        })
+        .await;
+        let client = Request::new().unwrap();
+        let Err(Error::HttpError(http)) = client
+            .request(Method::GET, &url)
+            .error_for_status(true)
+            .send()
+            .await
+        else {
+            panic!("expected an http error");
+        };
+        assert_eq!(http.status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(http.body, "partial");
+        assert!(http.truncated);
+    }
+}
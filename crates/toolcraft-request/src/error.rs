@@ -1,5 +1,11 @@
+use reqwest::{StatusCode, header::HeaderMap};
+use serde::Deserialize;
+use serde_json::Value;
 use thiserror::Error;
 
+/// Bytes of a non-2xx response body kept in [`HttpError::body`].
+pub const MAX_ERROR_BODY: usize = 4096;
+
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("io error: {0}")]
@@ -14,8 +20,54 @@ pub enum Error {
     #[error("url error: {0}")]
     UrlError(#[from] url::ParseError),
 
+    #[error("json error: {0}")]
+    JsonError(#[from] serde_json::Error),
+
+    #[error("http error: {0}")]
+    HttpError(Box<HttpError>),
+
     #[error("error message: {0}")]
     ErrorMessage(Box<str>),
 }
 
+impl Error {
+    /// Status of a non-2xx response, for [`Error::HttpError`] and reqwest status errors.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Error::HttpError(e) => Some(e.status),
+            Error::RequestError(e) => e.status(),
+            _ => None,
+        }
+    }
+}
+
 pub type Result<T, E = Error> = core::result::Result<T, E>;
+
+/// A non-2xx response, with at most [`MAX_ERROR_BODY`] bytes of its body.
+#[derive(Error, Debug, Clone)]
+#[error("{status}: {body}")]
+pub struct HttpError {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: String,
+    /// Whether `body` was cut at [`MAX_ERROR_BODY`].
+    pub truncated: bool,
+}
+
+impl HttpError {
+    /// Decode the body as the `CommonError` returned by toolcraft-axum-kit services.
+    pub fn common_error(&self) -> Option<CommonError> {
+        serde_json::from_str(&self.body).ok()
+    }
+}
+
+/// Error body of toolcraft-axum-kit services: `{"code", "message", "data", "request_id"}`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct CommonError {
+    pub code: i16,
+    pub message: String,
+    #[serde(default)]
+    pub data: Option<Value>,
+    #[serde(default)]
+    pub request_id: Option<String>,
+}
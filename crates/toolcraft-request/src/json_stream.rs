@@ -0,0 +1,350 @@
+use std::{collections::VecDeque, marker::PhantomData, pin::Pin};
+
+use futures_util::{Stream, StreamExt, stream};
+use serde::de::DeserializeOwned;
+
+use crate::{
+    error::{Error, Result},
+    response::ByteStream,
+};
+
+/// Largest NDJSON line or array element kept in memory by default.
+pub const DEFAULT_MAX_ITEM_SIZE: usize = 8 * 1024 * 1024;
+
+pub type JsonStream<T> = Pin<Box<dyn Stream<Item = Result<T>> + Send>>;
+
+/// Splits a body into the raw bytes of each JSON value.
+trait Splitter: Send + 'static {
+    fn feed(&mut self, chunk: &[u8], items: &mut VecDeque<Vec<u8>>) -> Result<()>;
+
+    fn finish(&mut self, items: &mut VecDeque<Vec<u8>>) -> Result<()>;
+}
+
+/// Decode newline-delimited JSON, one `T` per non-blank line. Lines longer than `max_line` end
+/// the stream with an error.
+pub fn ndjson<T>(bytes: ByteStream, max_line: usize) -> JsonStream<T>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    decode(
+        bytes,
+        Lines {
+            line: Vec::new(),
+            max_line,
+        },
+    )
+}
+
+/// Decode the elements of a top-level JSON array one `T` at a time. Elements longer than
+/// `max_element` end the stream with an error.
+pub fn json_array<T>(bytes: ByteStream, max_element: usize) -> JsonStream<T>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    decode(
+        bytes,
+        ArrayElements {
+            state: ArrayState::Start,
+            element: Vec::new(),
+            depth: 0,
+            in_string: false,
+            escape: false,
+            max_element,
+        },
+    )
+}
+
+struct Decoding<S, T> {
+    bytes: Option<ByteStream>,
+    splitter: S,
+    items: VecDeque<Vec<u8>>,
+    error: Option<Error>,
+    item: PhantomData<fn() -> T>,
+}
+
+fn decode<S, T>(bytes: ByteStream, splitter: S) -> JsonStream<T>
+where
+    S: Splitter,
+    T: DeserializeOwned + Send + 'static,
+{
+    let state: Decoding<S, T> = Decoding {
+        bytes: Some(bytes),
+        splitter,
+        items: VecDeque::new(),
+        error: None,
+        item: PhantomData,
+    };
+    Box::pin(stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(item) = state.items.pop_front() {
+                let item = serde_json::from_slice(&item).map_err(Error::from);
+                return Some((item, state));
+            }
+            if let Some(e) = state.error.take() {
+                return Some((Err(e), state));
+            }
+            let bytes = state.bytes.as_mut()?;
+            let result = match bytes.next().await {
+                Some(Ok(chunk)) => state.splitter.feed(&chunk, &mut state.items),
+                Some(Err(e)) => Err(e),
+                None => {
+                    state.bytes = None;
+                    state.splitter.finish(&mut state.items)
+                }
+            };
+            if let Err(e) = result {
+                state.bytes = None;
+                state.error = Some(e);
+            }
+        }
+    }))
+}
+
+fn too_large(max: usize) -> Error {
+    Error::ErrorMessage(format!("json item exceeds {max} bytes").into())
+}
+
+struct Lines {
+    line: Vec<u8>,
+    max_line: usize,
+}
+
+impl Lines {
+    fn push_line(&mut self, items: &mut VecDeque<Vec<u8>>) {
+        let line = std::mem::take(&mut self.line);
+        if !line.trim_ascii().is_empty() {
+            items.push_back(line);
+        }
+    }
+}
+
+impl Splitter for Lines {
+    fn feed(&mut self, chunk: &[u8], items: &mut VecDeque<Vec<u8>>) -> Result<()> {
+        let mut rest = chunk;
+        while let Some(end) = rest.iter().position(|&b| b == b'\n') {
+            if self.line.len() + end > self.max_line {
+                return Err(too_large(self.max_line));
+            }
+            self.line.extend_from_slice(&rest[.. end]);
+            self.push_line(items);
+            rest = &rest[end + 1 ..];
+        }
+        if self.line.len() + rest.len() > self.max_line {
+            return Err(too_large(self.max_line));
+        }
+        self.line.extend_from_slice(rest);
+        Ok(())
+    }
+
+    fn finish(&mut self, items: &mut VecDeque<Vec<u8>>) -> Result<()> {
+        self.push_line(items);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArrayState {
+    /// Before the opening `[`.
+    Start,
+    /// Expecting an element; `first` allows an immediate `]`.
+    Before {
+        first: bool,
+    },
+    InElement,
+    /// Expecting `,` or `]`.
+    After,
+    Done,
+}
+
+struct ArrayElements {
+    state: ArrayState,
+    element: Vec<u8>,
+    depth: usize,
+    in_string: bool,
+    escape: bool,
+    max_element: usize,
+}
+
+impl ArrayElements {
+    fn end_element(&mut self, items: &mut VecDeque<Vec<u8>>, next: ArrayState) {
+        items.push_back(std::mem::take(&mut self.element));
+        self.state = next;
+    }
+
+    fn element_byte(&mut self, b: u8, items: &mut VecDeque<Vec<u8>>) {
+        if self.in_string {
+            self.element.push(b);
+            if self.escape {
+                self.escape = false;
+            } else if b == b'\\' {
+                self.escape = true;
+            } else if b == b'"' {
+                self.in_string = false;
+                if self.depth == 0 {
+                    self.end_element(items, ArrayState::After);
+                }
+            }
+            return;
+        }
+        match b {
+            b'"' => {
+                self.element.push(b);
+                self.in_string = true;
+            }
+            b'{' | b'[' => {
+                self.element.push(b);
+                self.depth += 1;
+            }
+            // A scalar directly followed by the closing bracket.
+            b']' if self.depth == 0 => self.end_element(items, ArrayState::Done),
+            b'}' | b']' => {
+                self.element.push(b);
+                self.depth = self.depth.saturating_sub(1);
+                if self.depth == 0 {
+                    self.end_element(items, ArrayState::After);
+                }
+            }
+            b',' if self.depth == 0 => self.end_element(items, ArrayState::Before { first: false }),
+            b if self.depth == 0 && b.is_ascii_whitespace() => {
+                self.end_element(items, ArrayState::After)
+            }
+            b => self.element.push(b),
+        }
+    }
+}
+
+impl Splitter for ArrayElements {
+    fn feed(&mut self, chunk: &[u8], items: &mut VecDeque<Vec<u8>>) -> Result<()> {
+        for &b in chunk {
+            match self.state {
+                ArrayState::InElement => {
+                    self.element_byte(b, items);
+                    if self.element.len() > self.max_element {
+                        return Err(too_large(self.max_element));
+                    }
+                }
+                _ if b.is_ascii_whitespace() => {}
+                ArrayState::Start if b == b'[' => self.state = ArrayState::Before { first: true },
+                ArrayState::Before { first: true } if b == b']' => self.state = ArrayState::Done,
+                ArrayState::Before { .. } if b != b']' && b != b',' => {
+                    self.state = ArrayState::InElement;
+                    self.element_byte(b, items);
+                }
+                ArrayState::After if b == b',' => self.state = ArrayState::Before { first: false },
+                ArrayState::After if b == b']' => self.state = ArrayState::Done,
+                state => {
+                    return Err(Error::ErrorMessage(
+                        format!("unexpected byte {:?} in json array ({state:?})", b as char).into(),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, _items: &mut VecDeque<Vec<u8>>) -> Result<()> {
+        if self.state != ArrayState::Done {
+            return Err(Error::ErrorMessage("unexpected end of json array".into()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use serde::Deserialize;
+    use serde_json::{Value, json};
+
+    use super::*;
+
+    fn chunks(parts: &[&[u8]]) -> ByteStream {
+        let parts: Vec<Result<Bytes>> = parts
+            .iter()
+            .map(|part| Ok(Bytes::copy_from_slice(part)))
+            .collect();
+        Box::pin(stream::iter(parts))
+    }
+
+    async fn collect<T>(stream: JsonStream<T>) -> Vec<Result<T>> {
+        stream.collect().await
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Row {
+        id: u32,
+    }
+
+    #[tokio::test]
+    async fn test_ndjson_joins_split_lines() {
+        let stream = ndjson::<Row>(
+            chunks(&[b"{\"id\":1}\r\n{\"i", b"d\":2}\n\n  \n{\"id\"", b":3}"]),
+            64,
+        );
+        let rows: Vec<Row> = collect(stream)
+            .await
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(rows, [Row { id: 1 }, Row { id: 2 }, Row { id: 3 }]);
+    }
+
+    #[tokio::test]
+    async fn test_ndjson_caps_line_size() {
+        let stream = ndjson::<Row>(chunks(&[b"{\"id\":1}\n{\"id\":", b"22222222222"]), 16);
+        let items = collect(stream).await;
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].as_ref().unwrap(), &Row { id: 1 });
+        assert!(
+            items[1]
+                .as_ref()
+                .unwrap_err()
+                .to_string()
+                .contains("16 bytes")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_json_array_elements_at_every_split() {
+        let body: &[u8] = br#" [ {"id": 1, "tags": ["a]", "b\"}"]}, 2 ,"x,y", null, [[]], true ] "#;
+        let expected = vec![
+            json!({"id": 1, "tags": ["a]", "b\"}"]}),
+            json!(2),
+            json!("x,y"),
+            json!(null),
+            json!([[]]),
+            json!(true),
+        ];
+        for split in 0 .. body.len() {
+            let (a, b) = body.split_at(split);
+            let values: Vec<Value> = collect(json_array(chunks(&[a, b]), 1024))
+                .await
+                .into_iter()
+                .map(Result::unwrap)
+                .collect();
+            assert_eq!(values, expected, "split at {split}");
+        }
+        let empty = collect(json_array::<Value>(chunks(&[b"[ ]"]), 1024)).await;
+        assert!(empty.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_json_array_rejects_malformed_input() {
+        for body in [&b"{\"id\":1}"[..], b"[1,,2]", b"[1, 2", b"[1] 2"] {
+            let items = collect(json_array::<Value>(chunks(&[body]), 1024)).await;
+            assert!(
+                items.last().is_some_and(|item| item.is_err()),
+                "{}",
+                String::from_utf8_lossy(body)
+            );
+        }
+        let items = collect(json_array::<Value>(chunks(&[b"[\"", b"0123456789\"]"]), 8)).await;
+        assert!(
+            items[0]
+                .as_ref()
+                .unwrap_err()
+                .to_string()
+                .contains("8 bytes")
+        );
+    }
+}
@@ -1,9 +1,20 @@
+pub mod builder;
 pub mod client;
 pub mod error;
 pub mod header_map;
+pub mod json_stream;
 pub mod response;
+pub mod retry;
+pub mod sse;
+#[cfg(test)]
+mod stub;
 
+pub use builder::RequestBuilder;
 pub use client::{FormField, Request};
+pub use error::{CommonError, HttpError};
 pub use header_map::HeaderMap;
-pub use reqwest::header;
+pub use json_stream::JsonStream;
+pub use reqwest::{Method, header};
 pub use response::ByteStream;
+pub use retry::RetryPolicy;
+pub use sse::{Event, SseDecoder, SseReconnect, SseStream};
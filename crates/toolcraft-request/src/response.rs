@@ -3,7 +3,11 @@ use std::pin::Pin;
 use bytes::Bytes;
 use futures_util::{Stream, StreamExt};
 
-use crate::error::{Error, Result};
+use crate::{
+    error::{Error, HttpError, MAX_ERROR_BODY, Result},
+    json_stream::{self, DEFAULT_MAX_ITEM_SIZE, JsonStream},
+    sse::{self, SseStream},
+};
 
 pub type ByteStream = Pin<Box<dyn Stream<Item = crate::error::Result<Bytes>> + Send>>;
 pub struct Response {
@@ -32,6 +36,45 @@ impl Response {
         self.inner().status()
     }
 
+    /// Turn a non-2xx response into [`Error::HttpError`], keeping its status, headers and up to
+    /// [`MAX_ERROR_BODY`] bytes of its body. A body that fails to read is kept as far as it got and
+    /// marked truncated.
+    pub async fn error_for_status(mut self) -> Result<Self> {
+        let status = self.status();
+        if status.is_success() {
+            return Ok(self);
+        }
+        let headers = self.response.headers().clone();
+        let mut body = Vec::new();
+        let mut truncated = false;
+        loop {
+            match self.response.chunk().await {
+                Ok(Some(chunk)) => body.extend_from_slice(&chunk),
+                Ok(None) => break,
+                // Keep the status; what was read so far is all the body there is.
+                Err(_) => {
+                    truncated = true;
+                    break;
+                }
+            }
+            if body.len() > MAX_ERROR_BODY {
+                body.truncate(MAX_ERROR_BODY);
+                truncated = true;
+                break;
+            }
+        }
+        if truncated {
+            drop_partial_char(&mut body);
+        }
+        let body = String::from_utf8_lossy(&body).into_owned();
+        Err(Error::HttpError(Box::new(HttpError {
+            status,
+            headers,
+            body,
+            truncated,
+        })))
+    }
+
     /// Get the response body as a string.
     pub async fn text(self) -> Result<String> {
         self.response
@@ -64,4 +107,63 @@ impl Response {
             .map(|chunk_result| chunk_result.map_err(Error::from));
         Box::pin(stream)
     }
+
+    /// Decode a `text/event-stream` body into Server-Sent Events. Events over
+    /// [`DEFAULT_MAX_EVENT_SIZE`](sse::DEFAULT_MAX_EVENT_SIZE) end the stream with an error.
+    pub fn sse_stream(self) -> SseStream {
+        sse::decode(self.bytes_stream())
+    }
+
+    /// Decode a newline-delimited JSON body one line at a time as chunks arrive. Lines over
+    /// [`DEFAULT_MAX_ITEM_SIZE`] end the stream with an error.
+    pub fn ndjson_stream<T>(self) -> JsonStream<T>
+    where
+        T: serde::de::DeserializeOwned + Send + 'static,
+    {
+        self.ndjson_stream_with_limit(DEFAULT_MAX_ITEM_SIZE)
+    }
+
+    /// [`Response::ndjson_stream`] with lines capped at `max_line` bytes.
+    pub fn ndjson_stream_with_limit<T>(self, max_line: usize) -> JsonStream<T>
+    where
+        T: serde::de::DeserializeOwned + Send + 'static,
+    {
+        json_stream::ndjson(self.bytes_stream(), max_line)
+    }
+
+    /// Decode the elements of a top-level JSON array body one at a time as chunks arrive, without
+    /// buffering the whole array. Elements over [`DEFAULT_MAX_ITEM_SIZE`] end the stream with an
+    /// error.
+    pub fn json_array_stream<T>(self) -> JsonStream<T>
+    where
+        T: serde::de::DeserializeOwned + Send + 'static,
+    {
+        self.json_array_stream_with_limit(DEFAULT_MAX_ITEM_SIZE)
+    }
+
+    /// [`Response::json_array_stream`] with elements capped at `max_element` bytes.
+    pub fn json_array_stream_with_limit<T>(self, max_element: usize) -> JsonStream<T>
+    where
+        T: serde::de::DeserializeOwned + Send + 'static,
+    {
+        json_stream::json_array(self.bytes_stream(), max_element)
+    }
+}
+
+/// Drop a UTF-8 character cut in half at the end of a truncated body. Other invalid bytes are left
+/// to the lossy decode.
+fn drop_partial_char(body: &mut Vec<u8>) {
+    // A character is at most 4 bytes, so a cut one starts within the last 3.
+    let tail = body.len().saturating_sub(3);
+    let Some(start) = (tail .. body.len())
+        .rev()
+        .find(|&i| body[i] & 0b1100_0000 != 0b1000_0000)
+    else {
+        return;
+    };
+    if let Err(e) = std::str::from_utf8(&body[start ..])
+        && e.error_len().is_none()
+    {
+        body.truncate(start + e.valid_up_to());
+    }
 }
@@ -0,0 +1,344 @@
+use std::{
+    collections::hash_map::RandomState,
+    error::Error as _,
+    hash::{BuildHasher, Hasher},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use reqwest::{Method, StatusCode, header::HeaderMap};
+
+/// When and how often a failed request is sent again.
+///
+/// Only idempotent methods (`GET`, `HEAD`, `OPTIONS`, `PUT`, `DELETE`, `TRACE`) are retried unless
+/// [`RetryPolicy::with_non_idempotent`] is set. Requests whose body cannot be replayed, such as
+/// multipart uploads, are sent once.
+///
+/// # Example
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use toolcraft_request::{Request, RetryPolicy};
+///
+/// # fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let mut client = Request::new()?;
+/// client.set_retry_policy(
+///     RetryPolicy::new()
+///         .with_max_attempts(5)
+///         .with_backoff(Duration::from_millis(100), Duration::from_secs(5)),
+/// );
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    jitter: bool,
+    retry_after: bool,
+    max_retry_after: Duration,
+    statuses: Vec<StatusCode>,
+    transient_errors: bool,
+    non_idempotent: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RetryPolicy {
+    /// 3 attempts, exponential backoff from 200ms up to 10s with full jitter, retrying 429, 502,
+    /// 503 and 504 responses and transient connection errors, honouring `Retry-After` up to 60s.
+    pub fn new() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(10),
+            jitter: true,
+            retry_after: true,
+            max_retry_after: Duration::from_secs(60),
+            statuses: vec![
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+            transient_errors: true,
+            non_idempotent: false,
+        }
+    }
+
+    /// Total number of attempts, including the first one.
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Delay before the first retry, doubled on each further retry up to `max`.
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    /// Pick each delay at random between zero and the backoff, so clients do not retry in step.
+    pub fn with_jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Wait as long as a `Retry-After` header asks. Responses asking for more than `max` are
+    /// returned without retrying.
+    pub fn with_retry_after(mut self, honour: bool, max: Duration) -> Self {
+        self.retry_after = honour;
+        self.max_retry_after = max;
+        self
+    }
+
+    /// Response statuses that are retried.
+    pub fn with_statuses<I>(mut self, statuses: I) -> Self
+    where
+        I: IntoIterator<Item = StatusCode>,
+    {
+        self.statuses = statuses.into_iter().collect();
+        self
+    }
+
+    /// Retry connection failures, resets and timeouts.
+    pub fn with_transient_errors(mut self, retry: bool) -> Self {
+        self.transient_errors = retry;
+        self
+    }
+
+    /// Also retry `POST`, `PATCH` and other non-idempotent methods.
+    pub fn with_non_idempotent(mut self, retry: bool) -> Self {
+        self.non_idempotent = retry;
+        self
+    }
+
+    pub(crate) fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    pub(crate) fn allows(&self, method: &Method) -> bool {
+        self.non_idempotent
+            || matches!(
+                *method,
+                Method::GET
+                    | Method::HEAD
+                    | Method::OPTIONS
+                    | Method::PUT
+                    | Method::DELETE
+                    | Method::TRACE
+            )
+    }
+
+    /// Delay before retrying a response, or `None` if it must be returned as is.
+    pub(crate) fn delay_for_response(
+        &self,
+        status: StatusCode,
+        headers: &HeaderMap,
+        attempt: u32,
+    ) -> Option<Duration> {
+        if !self.statuses.contains(&status) {
+            return None;
+        }
+        match self.retry_after.then(|| retry_after(headers)).flatten() {
+            Some(wait) if wait > self.max_retry_after => None,
+            Some(wait) => Some(wait),
+            None => Some(self.backoff(attempt)),
+        }
+    }
+
+    /// Delay before retrying a failed send, or `None` if the error is not transient.
+    pub(crate) fn delay_for_error(&self, error: &reqwest::Error, attempt: u32) -> Option<Duration> {
+        (self.transient_errors && is_transient(error)).then(|| self.backoff(attempt))
+    }
+
+    /// Backoff after the `attempt`-th attempt (starting at 1).
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(16);
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(1 << exponent)
+            .min(self.max_backoff);
+        if !self.jitter {
+            return backoff;
+        }
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u32(attempt);
+        let fraction = (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64;
+        backoff.mul_f64(fraction)
+    }
+}
+
+fn is_transient(error: &reqwest::Error) -> bool {
+    if error.is_connect() || error.is_timeout() {
+        return true;
+    }
+    let mut source = error.source();
+    while let Some(cause) = source {
+        if let Some(io) = cause.downcast_ref::<std::io::Error>() {
+            return matches!(
+                io.kind(),
+                std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+                    | std::io::ErrorKind::BrokenPipe
+                    | std::io::ErrorKind::UnexpectedEof
+            );
+        }
+        source = cause.source();
+    }
+    false
+}
+
+/// `Retry-After` as delta-seconds or an HTTP date.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = parse_http_date(value)?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+    Some(Duration::from_secs(at.saturating_sub(now)))
+}
+
+/// Parse an IMF-fixdate such as `Sun, 06 Nov 1994 08:49:37 GMT` into unix seconds. Fields out of
+/// range, including years past 9999, are rejected so the arithmetic cannot overflow.
+fn parse_http_date(value: &str) -> Option<u64> {
+    let mut parts = value.split_whitespace().skip(1);
+    let day: u64 = parts
+        .next()?
+        .parse()
+        .ok()
+        .filter(|d| (1 ..= 31).contains(d))?;
+    let month = match parts.next()? {
+        "Jan" => 1,
+        "Feb" => 2,
+        "Mar" => 3,
+        "Apr" => 4,
+        "May" => 5,
+        "Jun" => 6,
+        "Jul" => 7,
+        "Aug" => 8,
+        "Sep" => 9,
+        "Oct" => 10,
+        "Nov" => 11,
+        "Dec" => 12,
+        _ => return None,
+    };
+    let year: i64 = parts
+        .next()?
+        .parse()
+        .ok()
+        .filter(|y| (1970 ..= 9999).contains(y))?;
+    let mut time = parts.next()?.split(':').map(|n| n.parse::<u64>().ok());
+    let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    // Days from civil, proleptic Gregorian calendar.
+    let (y, m) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let era = y.div_euclid(400);
+    let year_of_era = y - era * 400;
+    let day_of_year = (153 * m + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+    u64::try_from(days)
+        .ok()
+        .map(|days| days * 86_400 + hour * 3_600 + minute * 60 + second)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::{Request, stub};
+
+    /// Serves `failures` responses of `status` with `headers`, then `200 ok`.
+    async fn flaky_server(
+        failures: usize,
+        status: &'static str,
+        headers: &'static [(&'static str, &'static str)],
+    ) -> (String, Arc<AtomicUsize>) {
+        stub::serve(move |hit, _| {
+            if hit < failures {
+                stub::response(status, headers, b"")
+            } else {
+                stub::response("200 OK", &[], b"ok")
+            }
+        })
+        .await
+    }
+
+    const UNAVAILABLE: &str = "503 Service Unavailable";
+
+    fn client(url: &str) -> Request {
+        let mut client = Request::new().unwrap();
+        client.set_base_url(url).unwrap();
+        client.set_retry_policy(
+            RetryPolicy::new().with_backoff(Duration::from_millis(1), Duration::from_millis(5)),
+        );
+        client
+    }
+
+    #[tokio::test]
+    async fn test_retries_idempotent_requests_until_success() {
+        let (url, hits) = flaky_server(2, UNAVAILABLE, &[]).await;
+        let response = client(&url).get("flaky", None, None).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_post_is_not_retried_by_default() {
+        let (url, hits) = flaky_server(1, UNAVAILABLE, &[]).await;
+        let client = client(&url);
+        let response = client.post("flaky", &json!({}), None).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_long_retry_after_is_returned() {
+        let (url, hits) =
+            flaky_server(1, "429 Too Many Requests", &[("retry-after", "3600")]).await;
+        let response = client(&url).get("flaky", None, None).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_parse_http_date() {
+        assert_eq!(
+            parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"),
+            Some(784_111_777)
+        );
+        assert_eq!(parse_http_date("yesterday"), None);
+        for hostile in [
+            "Sun, 06 Nov 9223372036854775807 08:49:37 GMT",
+            "Sun, 99999999999 Nov 1994 08:49:37 GMT",
+            "Sun, 06 Nov 1994 18446744073709551615:49:37 GMT",
+            "Sun, 06 Nov 1994 08:60:37 GMT",
+        ] {
+            assert_eq!(parse_http_date(hostile), None, "{hostile}");
+        }
+    }
+}
@@ -0,0 +1,557 @@
+use std::{collections::VecDeque, pin::Pin, time::Duration};
+
+use futures_util::{Stream, StreamExt, stream};
+use reqwest::{Method, StatusCode};
+use serde_json::Value;
+
+use crate::{
+    client::Request,
+    error::{Error, Result},
+    header_map::HeaderMap,
+    response::ByteStream,
+};
+
+/// Largest line, or `data` of one event, kept in memory by default.
+pub const DEFAULT_MAX_EVENT_SIZE: usize = 8 * 1024 * 1024;
+
+pub type SseStream = Pin<Box<dyn Stream<Item = Result<Event>> + Send>>;
+
+/// One Server-Sent Event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    /// The `event` field, `message` when the server sent none.
+    pub event: String,
+    /// The `data` lines joined with `\n`.
+    pub data: String,
+    /// The last event id seen on the stream, which persists across events.
+    pub id: Option<String>,
+    /// The reconnection time sent with this event.
+    pub retry: Option<Duration>,
+}
+
+/// Incremental `text/event-stream` parser.
+///
+/// Bytes may be split anywhere between calls to [`SseDecoder::feed`], including inside a line
+/// or a `\r\n` pair.
+#[derive(Debug)]
+pub struct SseDecoder {
+    max_size: usize,
+    buf: Vec<u8>,
+    started: bool,
+    skip_lf: bool,
+    event: Option<String>,
+    data: String,
+    has_data: bool,
+    event_retry: Option<Duration>,
+    retry: Option<Duration>,
+    last_id: Option<String>,
+}
+
+impl Default for SseDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SseDecoder {
+    /// A decoder limited to [`DEFAULT_MAX_EVENT_SIZE`].
+    pub fn new() -> Self {
+        Self::with_max_size(DEFAULT_MAX_EVENT_SIZE)
+    }
+
+    /// A decoder that fails on lines, or event `data`, longer than `max_size` bytes.
+    pub fn with_max_size(max_size: usize) -> Self {
+        SseDecoder {
+            max_size,
+            buf: Vec::new(),
+            started: false,
+            skip_lf: false,
+            event: None,
+            data: String::new(),
+            has_data: false,
+            event_retry: None,
+            retry: None,
+            last_id: None,
+        }
+    }
+
+    /// Parse `chunk` and return the events it completes, or an error once a line or an event
+    /// exceeds the size limit.
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<Vec<Event>> {
+        const BOM: &[u8] = b"\xEF\xBB\xBF";
+        let held;
+        let mut chunk = chunk;
+        if !self.started {
+            // Hold back the first bytes until it is known whether they are a BOM.
+            self.buf.extend_from_slice(chunk);
+            if self.buf.len() < BOM.len() && BOM.starts_with(&self.buf) {
+                return Ok(Vec::new());
+            }
+            self.started = true;
+            held = std::mem::take(&mut self.buf);
+            chunk = held.strip_prefix(BOM).unwrap_or(&held);
+        }
+        if self.skip_lf && !chunk.is_empty() {
+            self.skip_lf = false;
+            chunk = chunk.strip_prefix(b"\n").unwrap_or(chunk);
+        }
+
+        let mut events = Vec::new();
+        let mut rest = chunk;
+        while let Some(end) = rest.iter().position(|&b| b == b'\n' || b == b'\r') {
+            if self.buf.len() + end > self.max_size {
+                return Err(self.too_large());
+            }
+            self.buf.extend_from_slice(&rest[.. end]);
+            let line = String::from_utf8_lossy(&self.buf).into_owned();
+            self.buf.clear();
+            self.line(&line, &mut events);
+            if self.data.len() > self.max_size {
+                return Err(self.too_large());
+            }
+
+            let crlf = rest[end] == b'\r';
+            rest = &rest[end + 1 ..];
+            if crlf {
+                match rest.first() {
+                    Some(b'\n') => rest = &rest[1 ..],
+                    Some(_) => {}
+                    None => self.skip_lf = true,
+                }
+            }
+        }
+        if self.buf.len() + rest.len() > self.max_size {
+            return Err(self.too_large());
+        }
+        self.buf.extend_from_slice(rest);
+        Ok(events)
+    }
+
+    /// Last event id seen, sent back as `Last-Event-ID` when reconnecting.
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.last_id.as_deref()
+    }
+
+    /// Reconnection time last requested by the server.
+    pub fn retry(&self) -> Option<Duration> {
+        self.retry
+    }
+
+    /// Drop a partially received event, as when the connection ends. The next bytes are read as
+    /// the start of a new stream, which may begin with a BOM.
+    pub fn reset(&mut self) {
+        self.buf.clear();
+        self.started = false;
+        self.skip_lf = false;
+        self.event = None;
+        self.data.clear();
+        self.has_data = false;
+        self.event_retry = None;
+    }
+
+    fn too_large(&self) -> Error {
+        Error::ErrorMessage(format!("sse event exceeds {} bytes", self.max_size).into())
+    }
+
+    fn line(&mut self, line: &str, events: &mut Vec<Event>) {
+        if line.is_empty() {
+            let event = self.event.take();
+            let retry = self.event_retry.take();
+            if self.has_data {
+                self.has_data = false;
+                events.push(Event {
+                    event: event.unwrap_or_else(|| String::from("message")),
+                    data: std::mem::take(&mut self.data),
+                    id: self.last_id.clone(),
+                    retry,
+                });
+            }
+            return;
+        }
+        if line.starts_with(':') {
+            return;
+        }
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "event" => self.event = (!value.is_empty()).then(|| value.to_string()),
+            "data" => {
+                if self.has_data {
+                    self.data.push('\n');
+                }
+                self.data.push_str(value);
+                self.has_data = true;
+            }
+            "id" if !value.contains('\0') => {
+                self.last_id = (!value.is_empty()).then(|| value.to_string());
+            }
+            "retry" if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) => {
+                self.event_retry = value.parse().ok().map(Duration::from_millis);
+                self.retry = self.event_retry.or(self.retry);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Decode a `text/event-stream` body into events. A partial event at the end is dropped, and an
+/// event over [`DEFAULT_MAX_EVENT_SIZE`] ends the stream with an error.
+pub fn decode(bytes: ByteStream) -> SseStream {
+    let state = (Some(bytes), SseDecoder::new(), VecDeque::new());
+    Box::pin(stream::unfold(
+        state,
+        |(mut bytes, mut decoder, mut queue)| async move {
+            loop {
+                if let Some(event) = queue.pop_front() {
+                    return Some((Ok(event), (bytes, decoder, queue)));
+                }
+                match bytes.as_mut()?.next().await? {
+                    Ok(chunk) => match decoder.feed(&chunk) {
+                        Ok(events) => queue.extend(events),
+                        Err(e) => return Some((Err(e), (None, decoder, queue))),
+                    },
+                    Err(e) => return Some((Err(e), (bytes, decoder, queue))),
+                }
+            }
+        },
+    ))
+}
+
+/// How [`Request::get_sse`] and [`Request::post_sse`] reconnect after the stream drops.
+#[derive(Debug, Clone)]
+pub struct SseReconnect {
+    max_retries: u32,
+    delay: Duration,
+}
+
+impl Default for SseReconnect {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SseReconnect {
+    /// Up to 5 reconnects in a row, 3 seconds apart unless the server sends `retry`.
+    pub fn new() -> Self {
+        SseReconnect {
+            max_retries: 5,
+            delay: Duration::from_secs(3),
+        }
+    }
+
+    /// Reconnects in a row before giving up; the count resets once an event arrives.
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Wait between reconnects when the server has not sent `retry`.
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+/// What to send on each (re)connection.
+pub(crate) struct SseRequest {
+    pub(crate) method: Method,
+    pub(crate) endpoint: String,
+    pub(crate) query: Vec<(String, String)>,
+    pub(crate) body: Option<Value>,
+    pub(crate) headers: Option<HeaderMap>,
+}
+
+impl SseRequest {
+    /// Send the request; `None` when the server answers `204 No Content` to stop the stream.
+    async fn connect(&self, client: &Request, last_id: Option<&str>) -> Result<Option<ByteStream>> {
+        let mut request = client
+            .request(self.method.clone(), &self.endpoint)
+            .query(self.query.clone())
+            .header(reqwest::header::ACCEPT, "text/event-stream")
+            .error_for_status(true);
+        if let Some(headers) = &self.headers {
+            request = request.headers(headers.clone());
+        }
+        if let Some(body) = &self.body {
+            request = request.json(body);
+        }
+        if let Some(id) = last_id {
+            request = request.header("last-event-id", id);
+        }
+        let response = request.send().await?;
+        Ok((response.status() != StatusCode::NO_CONTENT).then(|| response.bytes_stream()))
+    }
+}
+
+struct Connection {
+    client: Request,
+    request: SseRequest,
+    reconnect: Option<SseReconnect>,
+    bytes: Option<ByteStream>,
+    decoder: SseDecoder,
+    queue: VecDeque<Event>,
+    failures: u32,
+}
+
+impl Connection {
+    async fn next(&mut self) -> Option<Result<Event>> {
+        loop {
+            if let Some(event) = self.queue.pop_front() {
+                return Some(Ok(event));
+            }
+            let bytes = self.bytes.as_mut()?;
+            let error = match bytes.next().await {
+                Some(Ok(chunk)) => {
+                    let events = match self.decoder.feed(&chunk) {
+                        Ok(events) => events,
+                        // Reconnecting would resend the same oversized event.
+                        Err(e) => {
+                            self.bytes = None;
+                            return Some(Err(e));
+                        }
+                    };
+                    if !events.is_empty() {
+                        self.failures = 0;
+                    }
+                    self.queue.extend(events);
+                    continue;
+                }
+                Some(Err(e)) => Some(e),
+                None => None,
+            };
+            self.bytes = None;
+            self.decoder.reset();
+            if let Err(e) = self.reconnect(error).await {
+                return Some(Err(e));
+            }
+        }
+    }
+
+    /// Open a new connection after the current one ended, or return why the stream stops.
+    ///
+    /// Client errors other than `408` and `429`, such as an expired token, end the stream.
+    async fn reconnect(&mut self, mut error: Option<Error>) -> Result<()> {
+        let Some(reconnect) = self.reconnect.clone() else {
+            return error.map_or(Ok(()), Err);
+        };
+        while self.failures < reconnect.max_retries {
+            self.failures += 1;
+            tokio::time::sleep(self.decoder.retry().unwrap_or(reconnect.delay)).await;
+            match self
+                .request
+                .connect(&self.client, self.decoder.last_event_id())
+                .await
+            {
+                Ok(bytes) => {
+                    self.bytes = bytes;
+                    return Ok(());
+                }
+                Err(e) if e.status().is_some_and(is_final) => return Err(e),
+                Err(e) => error = Some(e),
+            }
+        }
+        error.map_or(Ok(()), Err)
+    }
+}
+
+fn is_final(status: StatusCode) -> bool {
+    status.is_client_error()
+        && status != StatusCode::REQUEST_TIMEOUT
+        && status != StatusCode::TOO_MANY_REQUESTS
+}
+
+/// Connect and decode events, reconnecting with `Last-Event-ID` when `reconnect` is set. Errors
+/// from the first connection are returned directly.
+pub(crate) async fn connect(
+    client: &Request,
+    request: SseRequest,
+    reconnect: Option<SseReconnect>,
+) -> Result<SseStream> {
+    let bytes = request.connect(client, None).await?;
+    let connection = Connection {
+        client: client.clone(),
+        request,
+        reconnect,
+        bytes,
+        decoder: SseDecoder::new(),
+        queue: VecDeque::new(),
+        failures: 0,
+    };
+    Ok(Box::pin(stream::unfold(
+        connection,
+        |mut connection| async move {
+            let item = connection.next().await?;
+            Some((item, connection))
+        },
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::stub;
+
+    fn feed_all(chunks: &[&[u8]]) -> Vec<Event> {
+        let mut decoder = SseDecoder::new();
+        chunks
+            .iter()
+            .flat_map(|chunk| decoder.feed(chunk).unwrap())
+            .collect()
+    }
+
+    fn event(event: &str, data: &str, id: Option<&str>) -> Event {
+        Event {
+            event: event.to_string(),
+            data: data.to_string(),
+            id: id.map(str::to_string),
+            retry: None,
+        }
+    }
+
+    #[test]
+    fn test_decoder_handles_split_chunks_multiline_data_and_comments() {
+        let whole: &[u8] = b"\xEF\xBB\xBFdata: first\r\n: keep-alive\r\ndata:second\r\n\r\n\
+            event: update\nid: 7\nretry: 1500\ndata: {\"n\":1}\n\n\
+            data: no newline at end";
+        let expected = vec![
+            event("message", "first\nsecond", None),
+            Event {
+                retry: Some(Duration::from_millis(1500)),
+                ..event("update", "{\"n\":1}", Some("7"))
+            },
+        ];
+        assert_eq!(feed_all(&[whole]), expected);
+
+        // Every split point, including inside `\r\n` and the BOM, gives the same events.
+        for split in 1 .. whole.len() {
+            let (a, b) = whole.split_at(split);
+            assert_eq!(feed_all(&[a, b]), expected, "split at {split}");
+        }
+    }
+
+    #[test]
+    fn test_decoder_strips_bom_after_reset() {
+        let mut decoder = SseDecoder::new();
+        assert!(decoder.feed(b"\xEF\xBB").unwrap().is_empty());
+        assert_eq!(
+            decoder.feed(b"\xBFdata: a\n\ndata: cut").unwrap(),
+            vec![event("message", "a", None)]
+        );
+        decoder.reset();
+        assert!(decoder.feed(b"\xEF").unwrap().is_empty());
+        assert_eq!(
+            decoder.feed(b"\xBB\xBFdata: b\n\n").unwrap(),
+            vec![event("message", "b", None)]
+        );
+    }
+
+    #[test]
+    fn test_decoder_skips_events_without_data() {
+        let mut decoder = SseDecoder::new();
+        let events = decoder
+            .feed(b"event: ping\nretry: 250\n\nid: 3\n\nevent:\ndata\n\n")
+            .unwrap();
+        assert_eq!(events, vec![event("message", "", Some("3"))]);
+        assert_eq!(decoder.retry(), Some(Duration::from_millis(250)));
+        assert_eq!(decoder.last_event_id(), Some("3"));
+    }
+
+    #[test]
+    fn test_decoder_caps_line_and_data_size() {
+        let mut decoder = SseDecoder::with_max_size(16);
+        assert_eq!(
+            decoder.feed(b"data: short\n\ndata: 0123").unwrap(),
+            vec![event("message", "short", None)]
+        );
+        let err = decoder.feed(b"456789abcdef").unwrap_err();
+        assert!(err.to_string().contains("16 bytes"), "{err}");
+
+        let mut decoder = SseDecoder::with_max_size(16);
+        let err = decoder
+            .feed(b"data: 0123456789\ndata: 0123456789\n")
+            .unwrap_err();
+        assert!(err.to_string().contains("16 bytes"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_stops_on_client_error() {
+        let (url, hits) = stub::serve(|hit, _| match hit {
+            0 => stub::response(
+                "200 OK",
+                &[("content-type", "text/event-stream")],
+                b"retry: 1\ndata: one\n\n",
+            ),
+            _ => stub::response("401 Unauthorized", &[], b"expired"),
+        })
+        .await;
+        let mut client = Request::new().unwrap();
+        client.set_base_url(&url).unwrap();
+
+        let items: Vec<Result<Event>> = client
+            .get_sse(
+                "events",
+                None,
+                None,
+                Some(SseReconnect::new().with_delay(Duration::from_millis(1))),
+            )
+            .await
+            .unwrap()
+            .collect()
+            .await;
+
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].as_ref().unwrap().data, "one");
+        assert_eq!(
+            items[1].as_ref().unwrap_err().status(),
+            Some(StatusCode::UNAUTHORIZED)
+        );
+        assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_post_sse_reconnects_with_last_event_id() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let requests = seen.clone();
+        let (url, _) = stub::serve(move |hit, request| {
+            requests
+                .lock()
+                .unwrap()
+                .push(String::from_utf8_lossy(request).to_ascii_lowercase());
+            let body: &[u8] = match hit {
+                0 => b"retry: 1\nid: 1\ndata: one\n\ndata: cut",
+                1 => b"id: 2\ndata: two\n\n",
+                _ => return stub::response("204 No Content", &[], b""),
+            };
+            stub::response("200 OK", &[("content-type", "text/event-stream")], body)
+        })
+        .await;
+        let mut client = Request::new().unwrap();
+        client.set_base_url(&url).unwrap();
+
+        let events: Vec<Event> = client
+            .post_sse(
+                "chat",
+                &serde_json::json!({"stream": true}),
+                None,
+                Some(SseReconnect::new().with_delay(Duration::from_millis(1))),
+            )
+            .await
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+            .await;
+
+        let data: Vec<&str> = events.iter().map(|e| e.data.as_str()).collect();
+        assert_eq!(data, ["one", "two"]);
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 3);
+        assert!(seen[0].contains("accept: text/event-stream"), "{}", seen[0]);
+        assert!(!seen[0].contains("last-event-id"), "{}", seen[0]);
+        assert!(seen[1].contains("last-event-id: 1"), "{}", seen[1]);
+        assert!(seen[2].contains("last-event-id: 2"), "{}", seen[2]);
+    }
+}
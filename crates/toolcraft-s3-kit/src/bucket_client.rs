@@ -1,15 +1,19 @@
 use std::{path::Path, sync::Arc};
 
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
+use futures_util::{Stream, StreamExt};
 use toolcraft_request::HeaderMap;
 use toolcraft_utils::{presign_get_object, presign_put_object, sign_request};
 
 use crate::{
     client::S3Client,
-    error::Result,
-    util::{ObjectInfo, check_status, parse_object_list, url_encode},
+    error::{Error, Result},
+    util::{ObjectInfo, check_status, extract_tag_values, parse_object_list, url_encode},
 };
 
+/// Size of each part of a multipart upload; S3 requires at least 5 MiB for all but the last.
+pub const MULTIPART_PART_SIZE: usize = 5 * 1024 * 1024;
+
 // ── Types ─────────────────────────────────────────────────────────────────────
 
 /// Object operations scoped to a specific bucket.
@@ -65,7 +69,7 @@ impl BucketClient {
             )
             .await?;
 
-        let xml = check_status(resp).await?.text().await?;
+        let xml = check_status("list_objects", resp).await?.text().await?;
         parse_object_list(&xml)
     }
 
@@ -96,7 +100,7 @@ impl BucketClient {
         } else {
             Some(headers)
         };
-        check_status(c.http.put_bytes(&url, data, headers).await?)
+        check_status("put_object", c.http.put_bytes(&url, data, headers).await?)
             .await
             .map(|_| ())
     }
@@ -137,7 +141,7 @@ impl BucketClient {
             None,
         );
 
-        let resp = check_status(c.http.get(&url, None, None).await?).await?;
+        let resp = check_status("get_object", c.http.get(&url, None, None).await?).await?;
         Ok(resp.bytes().await?)
     }
 
@@ -159,7 +163,7 @@ impl BucketClient {
             .delete(&c.url(&path), Some(c.signed_headers(&auth)?))
             .await?;
 
-        check_status(resp).await.map(|_| ())
+        check_status("delete_object", resp).await.map(|_| ())
     }
 
     /// Generate a presigned PUT URL for direct client-side upload.
@@ -176,3 +180,218 @@ impl BucketClient {
         )
     }
 }
+
+// ── Streaming uploads ─────────────────────────────────────────────────────────
+
+impl BucketClient {
+    /// Upload a stream of chunks without buffering the whole object, returning its size.
+    ///
+    /// Objects smaller than [`MULTIPART_PART_SIZE`] are sent with a single PUT; larger ones use a
+    /// multipart upload, holding at most one part in memory. If the stream or a request fails,
+    /// the multipart upload is aborted and the error returned. Pass `&mut stream` to inspect the
+    /// stream (for example, a hashing adapter) afterwards.
+    pub async fn upload_stream<S, E>(
+        &self,
+        key: &str,
+        mut stream: S,
+        content_type: Option<&str>,
+    ) -> Result<u64>
+    where
+        S: Stream<Item = Result<Bytes, E>> + Unpin,
+        E: Into<Error>,
+    {
+        let mut buffer = BytesMut::new();
+        let mut finished = false;
+        while buffer.len() < MULTIPART_PART_SIZE {
+            match stream.next().await {
+                Some(chunk) => buffer.extend_from_slice(&chunk.map_err(Into::into)?),
+                None => {
+                    finished = true;
+                    break;
+                }
+            }
+        }
+        if finished {
+            let size = buffer.len() as u64;
+            self.upload_bytes(key, buffer.freeze(), content_type)
+                .await?;
+            return Ok(size);
+        }
+
+        let upload_id = self.create_multipart_upload(key, content_type).await?;
+        let result = self
+            .upload_parts(key, &upload_id, buffer, &mut stream)
+            .await;
+        if result.is_err() {
+            let _ = self.abort_multipart_upload(key, &upload_id).await;
+        }
+        result
+    }
+
+    async fn upload_parts<S, E>(
+        &self,
+        key: &str,
+        upload_id: &str,
+        mut buffer: BytesMut,
+        stream: &mut S,
+    ) -> Result<u64>
+    where
+        S: Stream<Item = Result<Bytes, E>> + Unpin,
+        E: Into<Error>,
+    {
+        let mut etags = Vec::new();
+        let mut size = 0u64;
+        loop {
+            let chunk = stream.next().await.transpose().map_err(Into::into)?;
+            let finished = chunk.is_none();
+            if let Some(chunk) = chunk {
+                buffer.extend_from_slice(&chunk);
+            }
+            while buffer.len() >= MULTIPART_PART_SIZE
+                || (finished && (!buffer.is_empty() || etags.is_empty()))
+            {
+                let part = buffer.split_to(buffer.len().min(MULTIPART_PART_SIZE));
+                size += part.len() as u64;
+                let etag = self
+                    .upload_part(key, upload_id, etags.len() + 1, part.freeze())
+                    .await?;
+                etags.push(etag);
+            }
+            if finished {
+                break;
+            }
+        }
+        self.complete_multipart_upload(key, upload_id, &etags)
+            .await?;
+        Ok(size)
+    }
+
+    async fn create_multipart_upload(
+        &self,
+        key: &str,
+        content_type: Option<&str>,
+    ) -> Result<String> {
+        let mut headers = self.signed_object_headers("POST", key, "uploads=")?;
+        if let Some(ct) = content_type {
+            headers.insert("content-type", ct.to_string())?;
+        }
+        let resp = self
+            .inner
+            .http
+            .post_bytes(
+                &self.object_url(key, "uploads="),
+                Bytes::new(),
+                Some(headers),
+            )
+            .await?;
+        let xml = check_status("create_multipart_upload", resp)
+            .await?
+            .text()
+            .await?;
+        extract_tag_values(&xml, "UploadId")
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::Message("missing UploadId in response".into()))
+    }
+
+    async fn upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: usize,
+        data: Bytes,
+    ) -> Result<String> {
+        let query = format!(
+            "partNumber={part_number}&uploadId={}",
+            url_encode(upload_id)
+        );
+        let headers = self.signed_object_headers("PUT", key, &query)?;
+        let resp = self
+            .inner
+            .http
+            .put_bytes(&self.object_url(key, &query), data, Some(headers))
+            .await?;
+        let resp = check_status("upload_part", resp).await?;
+        resp.headers()
+            .get("etag")
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+            .ok_or_else(|| Error::Message("missing ETag for uploaded part".into()))
+    }
+
+    async fn complete_multipart_upload(
+        &self,
+        key: &str,
+        upload_id: &str,
+        etags: &[String],
+    ) -> Result<()> {
+        let query = format!("uploadId={}", url_encode(upload_id));
+        let parts: String = etags
+            .iter()
+            .enumerate()
+            .map(|(i, etag)| {
+                format!(
+                    "<Part><PartNumber>{}</PartNumber><ETag>{etag}</ETag></Part>",
+                    i + 1
+                )
+            })
+            .collect();
+        let body = format!("<CompleteMultipartUpload>{parts}</CompleteMultipartUpload>");
+        let headers = self.signed_object_headers("POST", key, &query)?;
+        let resp = self
+            .inner
+            .http
+            .post_bytes(&self.object_url(key, &query), body, Some(headers))
+            .await?;
+        // S3 can report a failed completion in the body of a 200 response.
+        let xml = check_status("complete_multipart_upload", resp)
+            .await?
+            .text()
+            .await?;
+        match extract_tag_values(&xml, "Error").into_iter().next() {
+            Some(error) => Err(Error::S3 {
+                status: 200,
+                message: extract_tag_values(&error, "Message")
+                    .into_iter()
+                    .next()
+                    .unwrap_or(error),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    async fn abort_multipart_upload(&self, key: &str, upload_id: &str) -> Result<()> {
+        let query = format!("uploadId={}", url_encode(upload_id));
+        let headers = self.signed_object_headers("DELETE", key, &query)?;
+        let resp = self
+            .inner
+            .http
+            .delete(&self.object_url(key, &query), Some(headers))
+            .await?;
+        check_status("abort_multipart_upload", resp)
+            .await
+            .map(|_| ())
+    }
+
+    fn object_path(&self, key: &str) -> String {
+        format!("/{}/{}", self.bucket, key.trim_start_matches('/'))
+    }
+
+    fn object_url(&self, key: &str, query: &str) -> String {
+        format!("{}?{query}", self.inner.url(&self.object_path(key)))
+    }
+
+    fn signed_object_headers(&self, method: &str, key: &str, query: &str) -> Result<HeaderMap> {
+        let c = &self.inner;
+        let auth = sign_request(
+            method,
+            &c.access_key,
+            &c.secret_key,
+            &c.host(),
+            &self.object_path(key),
+            query,
+            Some(&c.region),
+        );
+        c.signed_headers(&auth)
+    }
+}
@@ -28,6 +28,8 @@ impl S3Client {
     ) -> Result<Self> {
         let base_url = Url::parse(endpoint)?;
         let http = Request::new()?;
+        #[cfg(feature = "metrics")]
+        crate::metrics::register();
         Ok(Self {
             access_key: access_key.to_string(),
             secret_key: secret_key.to_string(),
@@ -68,7 +70,7 @@ impl S3Client {
             .put_bytes(&self.url(&path), body, Some(self.signed_headers(&auth)?))
             .await?;
 
-        check_status(resp).await.map(|_| ())
+        check_status("create_bucket", resp).await.map(|_| ())
     }
 
     pub async fn delete_bucket(&self, bucket: &str) -> Result<()> {
@@ -88,7 +90,7 @@ impl S3Client {
             .delete(&self.url(&path), Some(self.signed_headers(&auth)?))
             .await?;
 
-        check_status(resp).await.map(|_| ())
+        check_status("delete_bucket", resp).await.map(|_| ())
     }
 
     pub async fn list_buckets(&self) -> Result<Vec<String>> {
@@ -107,7 +109,7 @@ impl S3Client {
             .get(&self.url("/"), None, Some(self.signed_headers(&auth)?))
             .await?;
 
-        let xml = check_status(resp).await?.text().await?;
+        let xml = check_status("list_buckets", resp).await?.text().await?;
         parse_bucket_names(&xml)
     }
 }
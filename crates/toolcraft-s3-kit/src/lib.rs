@@ -2,9 +2,11 @@ pub mod bucket_client;
 pub mod client;
 pub mod config;
 pub mod error;
+#[cfg(feature = "metrics")]
+mod metrics;
 pub(crate) mod util;
 
-pub use bucket_client::BucketClient;
+pub use bucket_client::{BucketClient, MULTIPART_PART_SIZE};
 pub use client::S3Client;
 pub use config::S3BucketConfig;
 pub use util::ObjectInfo;
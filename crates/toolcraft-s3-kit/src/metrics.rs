@@ -0,0 +1,27 @@
+use std::sync::LazyLock;
+
+use prometheus::{IntCounterVec, register_int_counter_vec};
+
+/// S3 responses by operation and status class, in `prometheus::default_registry()`; `None` when
+/// that name is already taken there.
+static RESPONSES: LazyLock<Option<IntCounterVec>> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "toolcraft_s3_responses_total",
+        "S3 responses, by operation and status class",
+        &["operation", "status"]
+    )
+    .inspect_err(|e| tracing::warn!(error = %e, "S3 metrics are disabled"))
+    .ok()
+});
+
+/// Register the counter up front so it is exported before the first request.
+pub(crate) fn register() {
+    LazyLock::force(&RESPONSES);
+}
+
+pub(crate) fn record_response(operation: &str, status: u16) {
+    if let Some(counter) = RESPONSES.as_ref() {
+        let class = format!("{}xx", status / 100);
+        counter.with_label_values(&[operation, &class]).inc();
+    }
+}
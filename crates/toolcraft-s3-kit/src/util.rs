@@ -8,8 +8,13 @@ pub struct ObjectInfo {
     pub last_modified: String,
 }
 
-pub(crate) async fn check_status(resp: Response) -> Result<Response> {
+pub(crate) async fn check_status(
+    #[cfg_attr(not(feature = "metrics"), allow(unused_variables))] operation: &str,
+    resp: Response,
+) -> Result<Response> {
     let status = resp.status();
+    #[cfg(feature = "metrics")]
+    crate::metrics::record_response(operation, status.as_u16());
     if status.is_success() || status.as_u16() == 204 {
         return Ok(resp);
     }